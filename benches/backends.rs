@@ -0,0 +1,55 @@
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BatchSize,
+    BenchmarkId,
+    Criterion,
+};
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng,
+};
+use wswor::{
+    Backend,
+    StreamingWswor,
+};
+
+const ITEMS: usize = 100_000;
+
+fn backends(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let weights: Vec<f64> =
+        (0 .. ITEMS).map(|_| rng.random_range(0.0 .. 1.0)).collect();
+
+    for count in [100, 10_000, 50_000] {
+        let mut group = c.benchmark_group(format!("feed {count} of {ITEMS}"));
+
+        for backend in [Backend::Heap, Backend::KeySlab, Backend::Buffer] {
+            group.bench_function(
+                BenchmarkId::from_parameter(format!("{backend:?}")),
+                |b| {
+                    b.iter_batched(
+                        || StdRng::seed_from_u64(1),
+                        |mut rng| {
+                            let mut sampler: StreamingWswor<f64, usize> =
+                                StreamingWswor::new(count).backend(backend);
+
+                            for (i, &weight) in weights.iter().enumerate() {
+                                sampler.feed(i, weight, &mut rng).unwrap();
+                            }
+
+                            sampler.take().count()
+                        },
+                        BatchSize::SmallInput,
+                    )
+                },
+            );
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
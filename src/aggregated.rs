@@ -0,0 +1,317 @@
+// Based on sampling keys by their aggregated weight over unaggregated streams.
+//
+// - Cohen, E. (2018). Stream sampling framework and application for frequency
+//   cap statistics. ACM Transactions on Algorithms, 14(4), 1–40.
+// - Cohen, E., Duffield, N., Kaplan, H., Lund, C., & Thorup, M. (2007).
+//   Sketching unaggregated data streams for subpopulation-size queries.
+//   Proceedings of the 26th ACM SIGMOD-SIGACT-SIGART Symposium on Principles of
+//   Database Systems, 253–262.
+
+use std::{
+    collections::{
+        BinaryHeap,
+        HashMap,
+    },
+    hash::Hash,
+    vec,
+    vec::Vec,
+};
+
+use num::Float;
+
+use crate::{
+    hashed::{
+        self,
+        HashedId,
+    },
+    HasInvalidWeights,
+    WsworEntry,
+};
+
+// an element of a key that brought its score down
+struct Record<F> {
+    score: F,
+    // position of the element in the stream
+    index: usize,
+    weight: F,
+    // weight of the key counted before this element
+    before: F,
+}
+
+struct Held<F> {
+    // record lows since the key entered the sample, by decreasing score; the
+    // last one is the score of the key
+    records: Vec<Record<F>>,
+    // weight seen since the key last entered the sample
+    counted: F,
+}
+
+impl<F: Float> Held<F> {
+    fn score(&self) -> F {
+        self.records.last().unwrap().score
+    }
+
+    /// Drops the records that can't be the first element under the threshold
+    /// anymore, always keeping the last one.
+    fn prune(
+        &mut self,
+        threshold: F,
+    ) {
+        let stale = self
+            .records
+            .iter()
+            .take_while(|record| threshold <= record.score)
+            .count()
+            .min(self.records.len() - 1);
+
+        self.records.drain(.. stale);
+    }
+
+    /// The weight from the point where the key first went under the threshold
+    /// on, plus `1 / threshold` for the weight missed before it.
+    ///
+    /// Within the first element under the threshold, that point is at
+    /// `weight * score / threshold`, which spreads it like an exponential
+    /// with the threshold as the rate.
+    fn estimate(
+        &self,
+        threshold: Option<F>,
+    ) -> F {
+        let threshold = match threshold {
+            Some(threshold) => threshold,
+            None => return self.counted,
+        };
+
+        let first = self
+            .records
+            .iter()
+            .find(|record| record.score < threshold)
+            .unwrap_or_else(|| self.records.last().unwrap());
+
+        self.counted - first.before - first.weight * first.score / threshold
+            + threshold.recip()
+    }
+}
+
+/// One-pass Weighted Random Sampler Without Replacement over keys that show up
+/// many times with partial weights, sampling each key by its total weight.
+///
+/// Every element gets the key `Exp1 / weight` and a key's score is the
+/// smallest key among its elements, which makes it exponentially distributed
+/// with its total weight as the rate. The sample holds the keys with the
+/// smallest scores, so it is a PPSWOR sample over the aggregated weights, and
+/// only keys in the sample are stored.
+///
+/// The randomness of every element is derived from a hash of its key and its
+/// position in the stream under a seed, so the same stream always gives the
+/// same sample.
+///
+/// A key's weight is only counted from the element it entered the sample
+/// with. [`estimate`](AggregatedWswor::estimate) counts it from where the
+/// score of the key first went under the final threshold and adds
+/// `1 / threshold` to make up for the weight missed before, which gives
+/// unbiased estimates of the totals; estimates are exact until a key is first
+/// left out.
+pub struct AggregatedWswor<K, F: Float> {
+    count: usize,
+    seed: u64,
+    seen: usize,
+    held: HashMap<K, Held<F>>,
+    // may hold stale entries for keys whose score went down or that were
+    // evicted, they are skipped when they come up
+    heap: BinaryHeap<WsworEntry<F, K>>,
+    // smallest score that was left out of the sample
+    threshold: Option<F>,
+}
+
+impl<K, F> AggregatedWswor<K, F>
+where
+    K: Eq + Hash + Clone + HashedId,
+    F: Float,
+{
+    pub fn new(
+        count: usize,
+        seed: u64,
+    ) -> AggregatedWswor<K, F> {
+        AggregatedWswor {
+            count,
+            seed,
+            seen: 0,
+            held: HashMap::new(),
+            heap: BinaryHeap::new(),
+            threshold: None,
+        }
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter(
+        &mut self,
+        iter: impl Iterator<Item = (F, K)>,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, k) in iter {
+            self.feed(k, w)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds an element carrying part of the weight of a key, returning
+    /// whether the key is in the sample afterwards.
+    pub fn feed(
+        &mut self,
+        key: K,
+        weight: F,
+    ) -> Result<bool, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&weight)?;
+
+        let position = self.seen;
+        self.seen += 1;
+
+        if weight == F::zero() {
+            return Ok(self.held.contains_key(&key));
+        }
+
+        let score =
+            hashed::hashed_exp1::<F, _>(&(&key, position), self.seed) / weight;
+
+        if let Some(held) = self.held.get_mut(&key) {
+            if let Some(threshold) = self.threshold {
+                held.prune(threshold);
+            }
+
+            if score < held.score() {
+                held.records.push(Record {
+                    score,
+                    index: position,
+                    weight,
+                    before: held.counted,
+                });
+                self.heap.push(WsworEntry {
+                    weight: score,
+                    index: position,
+                    val: key,
+                });
+            }
+
+            held.counted = held.counted + weight;
+
+            return Ok(true);
+        }
+
+        if self.count == 0 {
+            self.leave_out(score);
+            return Ok(false);
+        }
+
+        if self.count <= self.held.len() {
+            if self.largest_score().is_some_and(|largest| largest <= score) {
+                self.leave_out(score);
+                return Ok(false);
+            }
+
+            let evicted = self.heap.pop().unwrap();
+            self.held.remove(&evicted.val);
+            self.leave_out(evicted.weight);
+        }
+
+        self.held.insert(
+            key.clone(),
+            Held {
+                records: vec![Record {
+                    score,
+                    index: position,
+                    weight,
+                    before: F::zero(),
+                }],
+                counted: weight,
+            },
+        );
+        self.heap.push(WsworEntry {
+            weight: score,
+            index: position,
+            val: key,
+        });
+
+        if 2 * self.count + 16 < self.heap.len() {
+            self.compact();
+        }
+
+        Ok(true)
+    }
+
+    fn leave_out(
+        &mut self,
+        score: F,
+    ) {
+        self.threshold = Some(
+            self.threshold
+                .map_or(score, |threshold| threshold.min(score)),
+        );
+    }
+
+    /// The largest score in the sample, after dropping the stale entries on
+    /// top of the heap.
+    fn largest_score(&mut self) -> Option<F> {
+        while let Some(top) = self.heap.peek() {
+            match self.held.get(&top.val) {
+                Some(held) if held.score() == top.weight => {
+                    return Some(top.weight);
+                },
+                _ => {
+                    self.heap.pop();
+                },
+            }
+        }
+
+        None
+    }
+
+    fn compact(&mut self) {
+        self.heap = self
+            .held
+            .iter()
+            .map(|(key, held)| WsworEntry {
+                weight: held.score(),
+                index: held.records.last().unwrap().index,
+                val: key.clone(),
+            })
+            .collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// The smallest score left out of the sample, if any was.
+    pub fn threshold_weight(&self) -> Option<F> {
+        self.threshold
+    }
+
+    /// Estimated total weight of a key in the sample.
+    pub fn estimate(
+        &self,
+        key: &K,
+    ) -> Option<F> {
+        self.held.get(key).map(|held| held.estimate(self.threshold))
+    }
+
+    /// The sampled keys with their estimated total weights.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, F)> {
+        self.held
+            .iter()
+            .map(|(key, held)| (key, held.estimate(self.threshold)))
+    }
+
+    pub fn take(self) -> impl Iterator<Item = (K, F)> {
+        let threshold = self.threshold;
+
+        self.held
+            .into_iter()
+            .map(move |(key, held)| (key, held.estimate(threshold)))
+    }
+}
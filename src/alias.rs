@@ -0,0 +1,176 @@
+// Based on Vose's method for building alias tables.
+//
+// - Vose, M. D. (1991). A linear algorithm for generating random numbers with a
+//   given distribution. IEEE Transactions on Software Engineering, 17(9),
+//   972–975.
+
+use alloc::{
+    vec,
+    vec::Vec,
+};
+
+use num::{
+    Float,
+    NumCast,
+    One,
+    Zero,
+};
+use rand::{
+    distr::{
+        Distribution,
+        StandardUniform,
+    },
+    Rng,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    HasInvalidWeights,
+    StreamingWswor,
+    Weight,
+};
+
+/// Weighted Random Sampler With Replacement over a fixed set of values.
+///
+/// Building the table takes `O(n)` time, after which every draw takes `O(1)`.
+pub struct AliasTable<W: Weight, T> {
+    values: Vec<T>,
+    weights: Vec<W>,
+    probability: Vec<W::Key>,
+    alias: Vec<usize>,
+}
+
+impl<W, T> AliasTable<W, T>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+    StandardUniform: Distribution<W::Key>,
+{
+    pub fn new(
+        iter: impl Iterator<Item = (W, T)>
+    ) -> Result<AliasTable<W, T>, HasInvalidWeights> {
+        let mut weights = Vec::new();
+        let mut values = Vec::new();
+
+        for (w, v) in iter {
+            w.validate()?;
+            weights.push(w);
+            values.push(v);
+        }
+
+        let keys: Vec<_> = weights.iter().map(Weight::to_key).collect();
+        let len = keys.len();
+        let heaviest = keys.iter().fold(W::Key::zero(), |max, &w| max.max(w));
+        let mut probability = vec![W::Key::one(); len];
+        let mut alias: Vec<_> = (0 .. len).collect();
+
+        // a table that can't be sampled from is left empty
+        if heaviest == W::Key::zero() {
+            probability.clear();
+            alias.clear();
+        }
+        else {
+            // the weights are summed relative to the heaviest one, so that the
+            // total can't overflow
+            let total = keys
+                .iter()
+                .fold(W::Key::zero(), |sum, &w| sum + w / heaviest);
+            let scale = <W::Key as NumCast>::from(len).unwrap() / total;
+            let mut scaled: Vec<_> =
+                keys.iter().map(|&w| w / heaviest * scale).collect();
+            let (mut small, mut large): (Vec<_>, Vec<_>) =
+                (0 .. len).partition(|&i| scaled[i] < W::Key::one());
+
+            while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+                small.pop();
+                probability[s] = scaled[s];
+                alias[s] = l;
+
+                // the large column gives away what the small one lacks
+                scaled[l] = (scaled[l] + scaled[s]) - W::Key::one();
+
+                if scaled[l] < W::Key::one() {
+                    large.pop();
+                    small.push(l);
+                }
+            }
+
+            // whatever is left over is only off from one by rounding errors
+            for i in small.into_iter().chain(large) {
+                probability[i] = W::Key::one();
+            }
+        }
+
+        Ok(AliasTable {
+            values,
+            weights,
+            probability,
+            alias,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Draws a single value. Returns `None` if there are no values or all of
+    /// them have zero weight.
+    pub fn sample<R: RngCore>(
+        &self,
+        rng: &mut R,
+    ) -> Option<&T> {
+        if self.probability.is_empty() {
+            return None;
+        }
+
+        let i = rng.random_range(0 .. self.probability.len());
+
+        if rng.sample::<W::Key, _>(StandardUniform) < self.probability[i] {
+            Some(&self.values[i])
+        }
+        else {
+            Some(&self.values[self.alias[i]])
+        }
+    }
+
+    /// Draws `count` values with replacement.
+    pub fn sample_n<'a, R: RngCore>(
+        &'a self,
+        count: usize,
+        rng: &'a mut R,
+    ) -> impl Iterator<Item = &'a T> + 'a {
+        (0 .. count).map_while(move |_| self.sample(rng))
+    }
+}
+
+impl<W, T> AliasTable<W, T>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    /// Draws `count` distinct values. The alias table can't do this by itself,
+    /// so the original weights are sent through a [`StreamingWswor`] instead,
+    /// which takes `O(n)` time.
+    pub fn without_replacement<R: RngCore>(
+        &self,
+        count: usize,
+        rng: &mut R,
+    ) -> impl Iterator<Item = &T> {
+        let mut sampler = StreamingWswor::new(count);
+
+        // the weights were already validated when the table was built
+        sampler
+            .feed_iter(
+                self.weights.iter().copied().zip(self.values.iter()),
+                rng,
+            )
+            .unwrap();
+
+        sampler.take()
+    }
+}
@@ -0,0 +1,235 @@
+use core::{
+    mem::MaybeUninit,
+    slice,
+};
+
+use num::Float;
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    scale_key,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// One-pass Weighted Random Sampler Without Replacement with a fixed capacity
+/// of `K` items, stored inline so that it never allocates. Without `std` it
+/// only needs the `libm` feature, not `alloc`.
+///
+/// Items get their keys the same way as in
+/// [`StreamingWswor`](crate::StreamingWswor), drawing exactly one `Exp1`
+/// variate per call to [`feed`](ArrayWswor::feed), so the two samplers keep
+/// the same items when fed the same stream with the same RNG state. Weights
+/// can be any [`Weight`], as for `StreamingWswor`.
+pub struct ArrayWswor<W: Weight, T, const K: usize> {
+    seen: usize,
+    len: usize,
+    // max-heap by key, of which the first `len` slots are initialized
+    heap: [MaybeUninit<WsworEntry<W::Key, T>>; K],
+}
+
+impl<W, T, const K: usize> ArrayWswor<W, T, K>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new() -> ArrayWswor<W, T, K> {
+        ArrayWswor {
+            seen: 0,
+            len: 0,
+            heap: [const { MaybeUninit::uninit() }; K],
+        }
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (W, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, v) in iter {
+            self.feed(v, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        let entry = WsworEntry {
+            val,
+            index: self.seen,
+            weight: scale_key(Exp1.sample(rng), weight.to_key()),
+        };
+
+        self.seen += 1;
+
+        if K == 0 {
+            return Ok(false);
+        }
+
+        if self.len < K {
+            self.heap[self.len].write(entry);
+            self.len += 1;
+            sift_up(self.entries_mut());
+
+            return Ok(true);
+        }
+
+        let entries = self.entries_mut();
+
+        if entries[0] < entry {
+            return Ok(false);
+        }
+
+        entries[0] = entry;
+        sift_down(entries);
+
+        Ok(true)
+    }
+
+    fn entries(&self) -> &[WsworEntry<W::Key, T>] {
+        // SAFETY: the first `len` slots are initialized
+        unsafe {
+            slice::from_raw_parts(
+                self.heap.as_ptr() as *const WsworEntry<W::Key, T>,
+                self.len,
+            )
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut [WsworEntry<W::Key, T>] {
+        // SAFETY: the first `len` slots are initialized
+        unsafe {
+            slice::from_raw_parts_mut(
+                self.heap.as_mut_ptr() as *mut WsworEntry<W::Key, T>,
+                self.len,
+            )
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        K <= self.len
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn threshold_weight(&self) -> Option<W::Key> {
+        self.entries().first().map(|entry| entry.weight)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries().iter().map(|entry| &entry.val)
+    }
+
+    pub fn take(mut self) -> impl Iterator<Item = T> {
+        core::iter::from_fn(move || self.pop_last())
+    }
+
+    /// The sampled items by increasing key, which is the order drawing them
+    /// one at a time with probability proportional to their weights, without
+    /// replacement, would give.
+    pub fn take_in_draw_order(mut self) -> impl Iterator<Item = T> {
+        let entries = self.entries_mut();
+
+        // heapsort into increasing keys, then flip so that popping from the
+        // back goes by increasing keys
+        for end in (1 .. entries.len()).rev() {
+            entries.swap(0, end);
+            sift_down(&mut entries[.. end]);
+        }
+        entries.reverse();
+
+        core::iter::from_fn(move || self.pop_last())
+    }
+
+    fn pop_last(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+
+        // SAFETY: the slot was initialized and is no longer counted in `len`,
+        // so it won't be read or dropped again
+        let entry = unsafe { self.heap[self.len].assume_init_read() };
+        Some(entry.val)
+    }
+}
+
+impl<W, T, const K: usize> Default for ArrayWswor<W, T, K>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Weight, T, const K: usize> Drop for ArrayWswor<W, T, K> {
+    fn drop(&mut self) {
+        for slot in &mut self.heap[.. self.len] {
+            // SAFETY: the first `len` slots are initialized
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+}
+
+/// Moves the last entry up to its place in the max-heap.
+fn sift_up<F: Float, T>(entries: &mut [WsworEntry<F, T>]) {
+    let mut child = entries.len() - 1;
+
+    while child > 0 {
+        let parent = (child - 1) / 2;
+
+        if entries[child] <= entries[parent] {
+            break;
+        }
+
+        entries.swap(child, parent);
+        child = parent;
+    }
+}
+
+/// Moves the first entry down to its place in the max-heap.
+fn sift_down<F: Float, T>(entries: &mut [WsworEntry<F, T>]) {
+    let mut parent = 0;
+
+    loop {
+        let left = 2 * parent + 1;
+        let right = left + 1;
+        let mut largest = parent;
+
+        if left < entries.len() && entries[largest] < entries[left] {
+            largest = left;
+        }
+        if right < entries.len() && entries[largest] < entries[right] {
+            largest = right;
+        }
+        if largest == parent {
+            break;
+        }
+
+        entries.swap(parent, largest);
+        parent = largest;
+    }
+}
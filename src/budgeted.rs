@@ -0,0 +1,143 @@
+use alloc::collections::BinaryHeap;
+
+use num::Num;
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    scale_key,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// One-pass Weighted Random Sampler Without Replacement that samples up to a
+/// total cost rather than a number of items.
+///
+/// Items get the same exponential keys as in
+/// [`StreamingWswor`](crate::StreamingWswor). The sample is the longest run of
+/// items, in order of increasing key, whose total cost fits within the budget.
+/// Equivalently, it is what drawing items one at a time with probability
+/// proportional to their weights gives when stopping right before the first
+/// draw that would go over the budget. An item that doesn't fit is not
+/// skipped over in favour of cheaper items drawn after it.
+pub struct BudgetedWswor<W: Weight, C, T> {
+    budget: C,
+    spent: C,
+    seen: usize,
+    heap: BinaryHeap<WsworEntry<W::Key, (C, T)>>,
+    // smallest key that was left out for not fitting; nothing with a larger
+    // key can ever make it into the sample
+    cutoff: Option<W::Key>,
+}
+
+impl<W, C, T> BudgetedWswor<W, C, T>
+where
+    W: Weight,
+    C: Num + PartialOrd + Copy,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new(budget: C) -> BudgetedWswor<W, C, T> {
+        BudgetedWswor {
+            budget,
+            spent: C::zero(),
+            seen: 0,
+            heap: BinaryHeap::new(),
+            cutoff: None,
+        }
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight or cost is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (W, C, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, c, v) in iter {
+            self.feed(v, c, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds an item with the given cost. Costs are validated like weights;
+    /// negative, NaN and infinite costs are rejected.
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        cost: C,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        match cost.partial_cmp(&C::zero()) {
+            None => Err(HasInvalidWeights::NaN)?,
+            Some(core::cmp::Ordering::Less) => {
+                Err(HasInvalidWeights::Negative)?
+            },
+            _ => {},
+        }
+
+        // only an infinite cost doesn't vanish when multiplied by zero, which
+        // integer costs never do
+        if cost * C::zero() != C::zero() {
+            Err(HasInvalidWeights::Infinite)?
+        }
+
+        let index = self.seen;
+        self.seen += 1;
+
+        let key = scale_key(Exp1.sample(rng), weight.to_key());
+
+        if self.cutoff.is_some_and(|cutoff| cutoff <= key) {
+            return Ok(false);
+        }
+
+        self.spent = self.spent + cost;
+        self.heap.push(WsworEntry {
+            weight: key,
+            index,
+            val: (cost, val),
+        });
+
+        // the items with the largest keys go until everything fits again
+        while self.budget < self.spent {
+            let entry = self.heap.pop().unwrap();
+            self.spent = self.spent - entry.val.0;
+            self.cutoff = Some(entry.weight);
+        }
+
+        Ok(self.cutoff.is_none_or(|cutoff| key < cutoff))
+    }
+
+    /// Total cost of the items in the sample.
+    pub fn spent(&self) -> C {
+        self.spent
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// The smallest key of the items left out for not fitting in the budget.
+    pub fn threshold_weight(&self) -> Option<W::Key> {
+        self.cutoff
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.heap.iter().map(|entry| &entry.val.1)
+    }
+
+    pub fn take(self) -> impl Iterator<Item = T> {
+        self.heap.into_iter().map(|entry| entry.val.1)
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::{
+        BinaryHeap,
+        HashMap,
+    },
+    hash::Hash,
+};
+
+use num::Float;
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    scale_key,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// One-pass Weighted Random Sampler Without Replacement that takes at most a
+/// fixed number of items from each category.
+///
+/// The sample is what a successive draw would give if it skipped over the
+/// items of categories that are already full: the items with the smallest keys
+/// once every category is cut down to its `per_category` smallest keys. Only
+/// the items that can still end up in that sample are kept, so an item can
+/// never be dropped in favour of one that later turns out not to fit.
+pub struct CategoryCappedWswor<C, W: Weight, T> {
+    count: usize,
+    per_category: usize,
+    seen: usize,
+    categories: HashMap<C, BinaryHeap<WsworEntry<W::Key, T>>>,
+    held: usize,
+    // largest key across all categories, kept up to date once the sample is
+    // full so rejecting an item doesn't need a scan over the categories
+    threshold: Option<W::Key>,
+}
+
+impl<C, W, T> CategoryCappedWswor<C, W, T>
+where
+    C: Eq + Hash,
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new(
+        count: usize,
+        per_category: usize,
+    ) -> CategoryCappedWswor<C, W, T> {
+        CategoryCappedWswor {
+            count,
+            per_category,
+            seen: 0,
+            categories: HashMap::new(),
+            held: 0,
+            threshold: None,
+        }
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (C, W, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (c, w, v) in iter {
+            self.feed(v, c, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        category: C,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        let index = self.seen;
+        self.seen += 1;
+
+        let entry = WsworEntry {
+            val,
+            index,
+            weight: scale_key(Exp1.sample(rng), weight.to_key()),
+        };
+
+        if self.count == 0 || self.per_category == 0 {
+            return Ok(false);
+        }
+
+        // the sample is full of items that all beat this one
+        if self.is_full() && self.threshold.is_some_and(|t| t < entry.weight) {
+            return Ok(false);
+        }
+
+        let heap = self.categories.entry(category).or_default();
+
+        // so is its category
+        if self.per_category <= heap.len()
+            && heap.peek().unwrap().weight < entry.weight
+        {
+            return Ok(false);
+        }
+
+        heap.push(entry);
+
+        if self.per_category < heap.len() {
+            heap.pop();
+        }
+        else {
+            self.held += 1;
+        }
+
+        if self.count < self.held {
+            self.pop_largest();
+        }
+
+        if self.is_full() {
+            self.threshold = self.threshold_weight();
+        }
+
+        Ok(true)
+    }
+
+    /// Drops the item with the largest key across all categories.
+    fn pop_largest(&mut self) {
+        let largest = self
+            .categories
+            .values_mut()
+            .max_by(|a, b| a.peek().cmp(&b.peek()));
+
+        if let Some(heap) = largest {
+            heap.pop();
+            self.held -= 1;
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count <= self.held
+    }
+
+    /// The largest key in the sample.
+    pub fn threshold_weight(&self) -> Option<W::Key> {
+        self.categories
+            .values()
+            .filter_map(|heap| heap.peek())
+            .map(|entry| entry.weight)
+            .reduce(Float::max)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.categories
+            .values()
+            .flat_map(|heap| heap.iter().map(|entry| &entry.val))
+    }
+
+    pub fn take(self) -> impl Iterator<Item = T> {
+        self.categories
+            .into_values()
+            .flat_map(|heap| heap.into_iter().map(|entry| entry.val))
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    vec,
+    vec::Vec,
+};
+
+use num::Float;
+use rand::{
+    distr::{
+        Distribution,
+        StandardUniform,
+    },
+    Rng,
+    RngCore,
+};
+
+use crate::HasInvalidWeights;
+
+/// A weighted set of keys whose weights can change at any time, backed by a
+/// Fenwick tree over the weights.
+///
+/// Insertions, removals and weight updates take `O(log n)` time, and a sample
+/// of `k` distinct keys takes `O(k log n)`.
+pub struct DynamicWeightedSet<K, F> {
+    slots: HashMap<K, usize>,
+    keys: Vec<Option<K>>,
+    weights: Vec<F>,
+    free: Vec<usize>,
+    positive: usize,
+    // 1-indexed; `tree[i]` holds the sum of the weights in the slots
+    // `i - lowbit(i) .. i`
+    tree: Vec<F>,
+    // updates since the tree was last rebuilt, to keep rounding errors from
+    // piling up
+    updates: usize,
+}
+
+impl<K, F> DynamicWeightedSet<K, F>
+where
+    K: Eq + Hash + Clone,
+    F: Float,
+    StandardUniform: Distribution<F>,
+{
+    pub fn new() -> DynamicWeightedSet<K, F> {
+        DynamicWeightedSet {
+            slots: HashMap::new(),
+            keys: Vec::new(),
+            weights: Vec::new(),
+            free: Vec::new(),
+            positive: 0,
+            tree: vec![F::zero()],
+            updates: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains_key(
+        &self,
+        key: &K,
+    ) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    pub fn weight(
+        &self,
+        key: &K,
+    ) -> Option<F> {
+        self.slots.get(key).map(|&slot| self.weights[slot])
+    }
+
+    pub fn total_weight(&self) -> F {
+        self.prefix(self.weights.len())
+    }
+
+    /// Inserts a key with the given weight, returning its previous weight if it
+    /// was already in the set. Weights that would make the total weight
+    /// infinite are rejected.
+    pub fn insert(
+        &mut self,
+        key: K,
+        weight: F,
+    ) -> Result<Option<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&weight)?;
+
+        if let Some(&slot) = self.slots.get(&key) {
+            self.check_total(slot, weight)?;
+            return Ok(Some(self.update(slot, weight)));
+        }
+
+        self.check_total(self.weights.len(), weight)?;
+
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => self.grow(),
+        };
+
+        self.keys[slot] = Some(key.clone());
+        self.slots.insert(key, slot);
+        self.update(slot, weight);
+
+        Ok(None)
+    }
+
+    /// Changes the weight of a key already in the set, returning its previous
+    /// weight. Keys that are not in the set are left out, and weights that
+    /// would make the total weight infinite are rejected.
+    pub fn set_weight(
+        &mut self,
+        key: &K,
+        weight: F,
+    ) -> Result<Option<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&weight)?;
+
+        match self.slots.get(key).copied() {
+            Some(slot) => {
+                self.check_total(slot, weight)?;
+                Ok(Some(self.update(slot, weight)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Removes a key from the set, returning its weight.
+    pub fn remove(
+        &mut self,
+        key: &K,
+    ) -> Option<F> {
+        let slot = self.slots.remove(key)?;
+        let weight = self.update(slot, F::zero());
+
+        self.keys[slot] = None;
+        self.free.push(slot);
+
+        Some(weight)
+    }
+
+    /// Samples up to `count` distinct keys, each draw picking a key with
+    /// probability proportional to its weight among those not yet picked.
+    ///
+    /// The keys are returned in the order they were drawn. If `count` exceeds
+    /// the number of keys with positive weight, keys with zero weight fill up
+    /// the rest of the sample in a uniformly random order.
+    pub fn sample_without_replacement<R: RngCore>(
+        &mut self,
+        count: usize,
+        rng: &mut R,
+    ) -> Vec<&K> {
+        let count = count.min(self.len());
+        let positive_count = count.min(self.positive);
+        let mut drawn = Vec::with_capacity(count);
+        let mut rebuilt = false;
+
+        // drawn keys have their weight zeroed out until the sample is complete
+        while drawn.len() < positive_count {
+            let target =
+                rng.sample::<F, _>(StandardUniform) * self.total_weight();
+            let mut slot = self.find(target);
+
+            // rounding can land the search on an empty slot or past the end;
+            // start over from exact sums the first time it does, and settle
+            // for the nearest positive slot after that
+            if self.weights.len() <= slot || self.weights[slot] == F::zero() {
+                if !rebuilt {
+                    self.rebuild();
+                    rebuilt = true;
+                    continue;
+                }
+
+                slot = self.nearest_positive(slot);
+            }
+
+            drawn.push((slot, self.update(slot, F::zero())));
+        }
+
+        for &(slot, weight) in drawn.iter() {
+            self.update(slot, weight);
+        }
+
+        let mut sampled: Vec<_> =
+            drawn.into_iter().map(|(slot, _)| slot).collect();
+
+        if sampled.len() < count {
+            let mut zero: Vec<_> = (0 .. self.weights.len())
+                .filter(|&slot| {
+                    self.keys[slot].is_some() && self.weights[slot] == F::zero()
+                })
+                .collect();
+
+            // partial Fisher-Yates shuffle over the zero weights
+            for i in 0 .. count - sampled.len() {
+                let j = rng.random_range(i .. zero.len());
+                zero.swap(i, j);
+                sampled.push(zero[i]);
+            }
+        }
+
+        sampled
+            .into_iter()
+            .map(|slot| self.keys[slot].as_ref().unwrap())
+            .collect()
+    }
+
+    /// Checks that setting the weight of a slot keeps the total weight finite,
+    /// since nothing could be drawn against an infinite total.
+    fn check_total(
+        &self,
+        slot: usize,
+        weight: F,
+    ) -> Result<(), HasInvalidWeights> {
+        let previous = self.weights.get(slot).copied().unwrap_or(F::zero());
+
+        if (self.total_weight() - previous + weight).is_finite() {
+            Ok(())
+        }
+        else {
+            Err(HasInvalidWeights::Infinite)
+        }
+    }
+
+    /// The positive slot closest to `slot`, looking before it first. There
+    /// has to be one.
+    fn nearest_positive(
+        &self,
+        slot: usize,
+    ) -> usize {
+        let positive = |&slot: &usize| self.weights[slot] > F::zero();
+        let len = self.weights.len();
+
+        (0 .. slot.min(len))
+            .rev()
+            .find(positive)
+            .or_else(|| (slot .. len).find(positive))
+            .unwrap()
+    }
+
+    /// Sets the weight of a slot, returning its previous weight.
+    fn update(
+        &mut self,
+        slot: usize,
+        weight: F,
+    ) -> F {
+        let previous = core::mem::replace(&mut self.weights[slot], weight);
+
+        match (previous > F::zero(), weight > F::zero()) {
+            (false, true) => self.positive += 1,
+            (true, false) => self.positive -= 1,
+            _ => {},
+        }
+
+        self.updates += 1;
+
+        if self.weights.len() < self.updates {
+            self.rebuild();
+            return previous;
+        }
+
+        let delta = weight - previous;
+        let mut i = slot + 1;
+
+        while i < self.tree.len() {
+            self.tree[i] = self.tree[i] + delta;
+            i += lowbit(i);
+        }
+
+        previous
+    }
+
+    /// Appends an empty slot, returning its index.
+    fn grow(&mut self) -> usize {
+        let slot = self.weights.len();
+        let i = slot + 1;
+
+        // the new node covers `i - lowbit(i) .. i`, all of which are already
+        // in the tree except for the new, empty slot
+        let sum = self.prefix(slot) - self.prefix(i - lowbit(i));
+
+        self.keys.push(None);
+        self.weights.push(F::zero());
+        self.tree.push(sum);
+
+        slot
+    }
+
+    /// Recomputes the tree from the weights in `O(n)`.
+    fn rebuild(&mut self) {
+        self.tree.truncate(1);
+        self.tree.extend(self.weights.iter().copied());
+
+        for i in 1 .. self.tree.len() {
+            let parent = i + lowbit(i);
+
+            if parent < self.tree.len() {
+                self.tree[parent] = self.tree[parent] + self.tree[i];
+            }
+        }
+
+        self.updates = 0;
+    }
+
+    /// Sum of the weights of the first `len` slots.
+    fn prefix(
+        &self,
+        len: usize,
+    ) -> F {
+        let mut sum = F::zero();
+        let mut i = len;
+
+        while i > 0 {
+            sum = sum + self.tree[i];
+            i -= lowbit(i);
+        }
+
+        sum
+    }
+
+    /// The first slot at which the cumulative weight exceeds `target`.
+    fn find(
+        &self,
+        mut target: F,
+    ) -> usize {
+        let len = self.weights.len();
+        let mut pos = 0;
+        let mut step = match len {
+            0 => 0,
+            len => 1 << len.ilog2(),
+        };
+
+        while step > 0 {
+            if pos + step <= len && self.tree[pos + step] <= target {
+                pos += step;
+                target = target - self.tree[pos];
+            }
+
+            step >>= 1;
+        }
+
+        pos
+    }
+}
+
+impl<K, F> Default for DynamicWeightedSet<K, F>
+where
+    K: Eq + Hash + Clone,
+    F: Float,
+    StandardUniform: Distribution<F>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lowbit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
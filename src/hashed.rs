@@ -0,0 +1,234 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::hash::Hasher;
+
+use num::Float;
+
+/// An ID that randomness can be derived from, as in
+/// [`StreamingWswor::feed_hashed`](crate::StreamingWswor::feed_hashed).
+///
+/// Unlike with `Hash`, whose impls for std types are free to feed different
+/// bytes to the hasher in a later Rust release, what is hashed for an ID is
+/// fixed by this crate: integers go in as 64-bit words (two for 128-bit ones),
+/// sign-extended for signed types, and strings and byte strings as their
+/// bytes. Tuples hash their fields in order. Other types can implement this by
+/// writing one of those to the hasher.
+pub trait HashedId {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    );
+}
+
+macro_rules! word_id {
+    ($($t:ty => $word:ty),*) => {
+        $(
+            impl HashedId for $t {
+                fn write_id<H: Hasher>(
+                    &self,
+                    hasher: &mut H,
+                ) {
+                    hasher.write_u64(*self as $word as u64);
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! wide_id {
+    ($($t:ty),*) => {
+        $(
+            impl HashedId for $t {
+                fn write_id<H: Hasher>(
+                    &self,
+                    hasher: &mut H,
+                ) {
+                    hasher.write_u64(*self as u64);
+                    hasher.write_u64((*self >> 64) as u64);
+                }
+            }
+        )*
+    };
+}
+
+word_id!(
+    u8 => u64, u16 => u64, u32 => u64, u64 => u64, usize => u64,
+    i8 => i64, i16 => i64, i32 => i64, i64 => i64, isize => i64
+);
+wide_id!(u128, i128);
+
+impl HashedId for [u8] {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        hasher.write(self);
+    }
+}
+
+impl<const N: usize> HashedId for [u8; N] {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        hasher.write(self);
+    }
+}
+
+impl HashedId for str {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl HashedId for Vec<u8> {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        hasher.write(self);
+    }
+}
+
+impl HashedId for String {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl<T: HashedId + ?Sized> HashedId for &T {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        (**self).write_id(hasher);
+    }
+}
+
+impl<A: HashedId, B: HashedId> HashedId for (A, B) {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        self.0.write_id(hasher);
+        self.1.write_id(hasher);
+    }
+}
+
+impl<A: HashedId, B: HashedId, C: HashedId> HashedId for (A, B, C) {
+    fn write_id<H: Hasher>(
+        &self,
+        hasher: &mut H,
+    ) {
+        self.0.write_id(hasher);
+        self.1.write_id(hasher);
+        self.2.write_id(hasher);
+    }
+}
+
+/// A 64-bit hasher whose output depends only on the seed and the words and
+/// bytes written to it, never on the platform or the process. Fed through
+/// [`HashedId`], whose bytes are fixed by this crate, the randomness derived
+/// from it can be reproduced anywhere.
+pub(crate) struct SeededHasher {
+    state: u64,
+}
+
+impl SeededHasher {
+    pub(crate) fn new(seed: u64) -> SeededHasher {
+        SeededHasher {
+            state: mix(seed ^ 0x9e37_79b9_7f4a_7c15),
+        }
+    }
+}
+
+impl Hasher for SeededHasher {
+    fn write(
+        &mut self,
+        bytes: &[u8],
+    ) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[.. chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+
+        // so that trailing zero bytes still make a difference
+        self.write_u64(bytes.len() as u64);
+    }
+
+    fn write_u8(
+        &mut self,
+        i: u8,
+    ) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u16(
+        &mut self,
+        i: u16,
+    ) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u32(
+        &mut self,
+        i: u32,
+    ) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u64(
+        &mut self,
+        i: u64,
+    ) {
+        self.state = mix(self.state.rotate_left(23) ^ i);
+    }
+
+    fn write_usize(
+        &mut self,
+        i: usize,
+    ) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        mix(self.state)
+    }
+}
+
+/// The finalizer of SplitMix64.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A uniform variate in `(0, 1]` derived from the hash of `id` under `seed`.
+pub(crate) fn hashed_unit<I: HashedId + ?Sized>(
+    id: &I,
+    seed: u64,
+) -> f64 {
+    let mut hasher = SeededHasher::new(seed);
+    id.write_id(&mut hasher);
+
+    // the top 53 bits, shifted by one so that zero can't come up
+    ((hasher.finish() >> 11) + 1) as f64 / (1u64 << 53) as f64
+}
+
+/// An `Exp1` variate derived from the hash of `id` under `seed`.
+pub(crate) fn hashed_exp1<F: Float, I: HashedId + ?Sized>(
+    id: &I,
+    seed: u64,
+) -> F {
+    F::from(-hashed_unit(id, seed).ln()).unwrap()
+}
@@ -0,0 +1,164 @@
+// Based on Ioffe's Improved Consistent Weighted Sampling.
+//
+// - Ioffe, S. (2010). Improved consistent sampling, weighted minhash and L1
+//   sketching. 2010 IEEE International Conference on Data Mining, 246–255.
+
+use alloc::{
+    vec,
+    vec::Vec,
+};
+use core::hash::Hasher;
+
+use num::Float;
+
+use crate::{
+    hashed::{
+        hashed_unit,
+        HashedId,
+        SeededHasher,
+    },
+    HasInvalidWeights,
+};
+
+#[derive(Clone, Copy)]
+struct IcwsSlot {
+    a: f64,
+    key: u64,
+    t: i64,
+}
+
+/// Sketch of a weighted set whose collisions with another sketch estimate the
+/// weighted Jaccard similarity `sum(min(w, v)) / sum(max(w, v))` of the two
+/// sets.
+///
+/// Each element of the signature is a consistent weighted sample of the set:
+/// a `(key, t)` pair picked with probability proportional to the weight of the
+/// key, in such a way that two sets pick the same pair with probability equal
+/// to their weighted Jaccard similarity. All randomness is derived from hashes
+/// of the keys, so sketches built with the same length and seed are comparable
+/// no matter where or in which order they were built.
+pub struct IcwsSketch {
+    seed: u64,
+    slots: Vec<IcwsSlot>,
+}
+
+impl IcwsSketch {
+    pub fn new(
+        len: usize,
+        seed: u64,
+    ) -> IcwsSketch {
+        IcwsSketch {
+            seed,
+            slots: vec![
+                IcwsSlot {
+                    a: f64::INFINITY,
+                    key: 0,
+                    t: 0,
+                };
+                len
+            ],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<F: Float, I: HashedId>(
+        &mut self,
+        iter: impl Iterator<Item = (F, I)>,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, k) in iter {
+            self.feed(&k, w)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a key with its weight to the set. Every key should be fed once,
+    /// with its total weight; keys with zero weight are not part of the set.
+    pub fn feed<F: Float, I: HashedId + ?Sized>(
+        &mut self,
+        key: &I,
+        weight: F,
+    ) -> Result<(), HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&weight)?;
+
+        if weight == F::zero() {
+            return Ok(());
+        }
+
+        let mut hasher = SeededHasher::new(self.seed);
+        key.write_id(&mut hasher);
+        let key = hasher.finish();
+        let ln_weight = weight.to_f64().unwrap().ln();
+
+        for (j, slot) in self.slots.iter_mut().enumerate() {
+            let unit = |i: u64| hashed_unit(&(key, j as u64, i), self.seed);
+
+            // r, c ~ Gamma(2, 1) and beta ~ Uniform(0, 1)
+            let r = -(unit(0) * unit(1)).ln();
+            let c = -(unit(2) * unit(3)).ln();
+            let beta = unit(4);
+
+            let t = (ln_weight / r + beta).floor();
+            let ln_y = r * (t - beta);
+            let a = c / (ln_y + r).exp();
+
+            if a < slot.a {
+                *slot = IcwsSlot {
+                    a,
+                    key,
+                    t: t as i64,
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The signature as `(key hash, t)` pairs. Slots that haven't seen any key
+    /// yet are `None`.
+    pub fn signature(&self) -> impl Iterator<Item = Option<(u64, i64)>> + '_ {
+        self.slots.iter().map(|slot| {
+            if slot.a.is_finite() {
+                Some((slot.key, slot.t))
+            }
+            else {
+                None
+            }
+        })
+    }
+
+    /// Estimates the weighted Jaccard similarity of the two sets as the
+    /// fraction of matching signature elements.
+    ///
+    /// # Panics
+    ///
+    /// If the sketches differ in length or seed.
+    pub fn similarity(
+        &self,
+        other: &IcwsSketch,
+    ) -> f64 {
+        assert_eq!(self.len(), other.len(), "sketch lengths differ");
+        assert_eq!(self.seed, other.seed, "sketch seeds differ");
+
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let matches = self
+            .signature()
+            .zip(other.signature())
+            .filter(|(a, b)| a.is_some() && a == b)
+            .count();
+
+        matches as f64 / self.len() as f64
+    }
+}
@@ -0,0 +1,409 @@
+// Based on the algorithms compared in Müller (2016), for weights that are
+// already in memory.
+//
+// - Müller, K. (2016). Accelerating weighted random sampling without
+//   replacement. Arbeitsberichte Verkehrs- Und Raumplanung, 1141.
+
+use alloc::{
+    collections::{
+        BTreeMap,
+        BinaryHeap,
+    },
+    vec,
+    vec::Vec,
+};
+
+use num::{
+    Float,
+    Zero,
+};
+use rand::{
+    distr::{
+        Distribution,
+        StandardUniform,
+    },
+    Rng,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    uniform,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// Algorithm used by [`sample_indices`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexAlgorithm {
+    /// Picks between [`Rejection`](Self::Rejection) and
+    /// [`ExpJumps`](Self::ExpJumps) depending on the fraction of the items
+    /// that are sampled and on how much of the weight they can hold.
+    Auto,
+    /// Draws with replacement from the cumulative weights and throws away
+    /// repeats. Fast when only a small fraction of the items, holding a small
+    /// fraction of the weight, is sampled.
+    Rejection,
+    /// Draws one item at a time from the remaining items, scanning them in
+    /// order of decreasing weight. This is `crank` in Müller (2016) and what R
+    /// does for `sample.int`. Takes `O(nk)` time.
+    Rank,
+    /// One pass over the weights with exponential keys, jumping over the items
+    /// that would not make it into the reservoir.
+    ExpJumps,
+    /// Groups the items into buckets of weights within a power of two of each
+    /// other, picks a bucket by its total weight and then an item within the
+    /// bucket by rejection.
+    Bucket,
+}
+
+/// Samples `count` distinct indices of `weights`, each draw picking an index
+/// with probability proportional to its weight among those not yet picked.
+///
+/// The indices are returned in the order they were drawn. If `count` exceeds
+/// the number of positive weights, indices with zero weight fill up the rest
+/// of the sample in a uniformly random order.
+pub fn sample_indices<W, R>(
+    weights: &[W],
+    count: usize,
+    algorithm: IndexAlgorithm,
+    rng: &mut R,
+) -> Result<Vec<usize>, HasInvalidWeights>
+where
+    W: Weight,
+    R: RngCore,
+    Exp1: Distribution<W::Key>,
+    StandardUniform: Distribution<W::Key>,
+{
+    for weight in weights {
+        weight.validate()?;
+    }
+
+    let mut keys: Vec<W::Key> = weights.iter().map(Weight::to_key).collect();
+
+    // weights close to the largest float can add up to infinity, which would
+    // leave nothing to draw against, so they are scaled down by a power of two
+    // first - only weights too light to ever be drawn against them are lost
+    if let Some(scale) = scale(&keys) {
+        for key in keys.iter_mut() {
+            *key = *key / scale;
+        }
+    }
+
+    let weights = &keys[..];
+
+    let (positive, mut zero): (Vec<_>, Vec<_>) =
+        (0 .. weights.len()).partition(|&i| weights[i] > W::Key::zero());
+    let count = count.min(weights.len());
+    let positive_count = count.min(positive.len());
+
+    let algorithm = match algorithm {
+        IndexAlgorithm::Auto
+            if positive_count * 10 <= positive.len()
+                && is_spread(weights, &positive, positive_count) =>
+        {
+            IndexAlgorithm::Rejection
+        },
+        IndexAlgorithm::Auto => IndexAlgorithm::ExpJumps,
+        algorithm => algorithm,
+    };
+
+    let mut sampled = match algorithm {
+        IndexAlgorithm::Rejection => {
+            rejection(weights, &positive, positive_count, rng)
+        },
+        IndexAlgorithm::Rank => rank(weights, &positive, positive_count, rng),
+        IndexAlgorithm::ExpJumps => {
+            exp_jumps(weights, &positive, positive_count, rng)
+        },
+        IndexAlgorithm::Bucket => {
+            bucket(weights, &positive, positive_count, rng)
+        },
+        IndexAlgorithm::Auto => unreachable!(),
+    };
+
+    // partial Fisher-Yates shuffle over the zero weights
+    for i in 0 .. count - positive_count {
+        let j = rng.random_range(i .. zero.len());
+        zero.swap(i, j);
+        sampled.push(zero[i]);
+    }
+
+    Ok(sampled)
+}
+
+/// The power of two to divide the weights by if their total overflows.
+fn scale<F: Float>(weights: &[F]) -> Option<F> {
+    let total = weights.iter().fold(F::zero(), |sum, &weight| sum + weight);
+
+    if total.is_finite() {
+        return None;
+    }
+
+    let heaviest = weights
+        .iter()
+        .fold(F::zero(), |max, &weight| max.max(weight));
+    let two = F::one() + F::one();
+
+    Some(two.powi(binary_exponent(heaviest)))
+}
+
+/// Whether `count` items can hold at most half of the weight, even if they are
+/// all as heavy as the heaviest one, so that draws with replacement rarely
+/// land on an item that was already taken.
+fn is_spread<F: Float>(
+    weights: &[F],
+    positive: &[usize],
+    count: usize,
+) -> bool {
+    let (total, heaviest) = positive
+        .iter()
+        .fold((F::zero(), F::zero()), |(total, heaviest), &i| {
+            (total + weights[i], heaviest.max(weights[i]))
+        });
+
+    match F::from(2 * count) {
+        Some(count) => heaviest * count <= total,
+        None => false,
+    }
+}
+
+fn rejection<F, R>(
+    weights: &[F],
+    positive: &[usize],
+    count: usize,
+    rng: &mut R,
+) -> Vec<usize>
+where
+    F: Float,
+    R: RngCore,
+    StandardUniform: Distribution<F>,
+{
+    let mut remaining = positive.to_vec();
+    let mut sampled = Vec::with_capacity(count);
+
+    while sampled.len() < count {
+        // the cumulative weights are rebuilt without the taken items once
+        // those hold half of the weight, so that draws keep landing on items
+        // that are left - light items behind heavy ones can't be hit at all
+        // once their cumulative weights round to the same value
+        let cumulative: Vec<F> = remaining
+            .iter()
+            .scan(F::zero(), |sum, &i| {
+                *sum = *sum + weights[i];
+                Some(*sum)
+            })
+            .collect();
+        let total = *cumulative.last().unwrap();
+
+        let mut taken = vec![false; remaining.len()];
+        let mut taken_weight = F::zero();
+
+        while sampled.len() < count && taken_weight + taken_weight <= total {
+            let target = rng.sample::<F, _>(StandardUniform) * total;
+            let j = cumulative.partition_point(|&sum| sum <= target);
+
+            // rounding can put the target past the last cumulative weight
+            if remaining.len() <= j || taken[j] {
+                continue;
+            }
+
+            taken[j] = true;
+            taken_weight = taken_weight + weights[remaining[j]];
+            sampled.push(remaining[j]);
+        }
+
+        let mut taken = taken.into_iter();
+        remaining.retain(|_| !taken.next().unwrap());
+    }
+
+    sampled
+}
+
+fn rank<F, R>(
+    weights: &[F],
+    positive: &[usize],
+    count: usize,
+    rng: &mut R,
+) -> Vec<usize>
+where
+    F: Float,
+    R: RngCore,
+    StandardUniform: Distribution<F>,
+{
+    // heavier items first so the scans end early
+    let mut remaining = positive.to_vec();
+    remaining.sort_by(|&a, &b| weights[b].partial_cmp(&weights[a]).unwrap());
+
+    let mut total =
+        remaining.iter().fold(F::zero(), |sum, &i| sum + weights[i]);
+    let mut sampled = Vec::with_capacity(count);
+
+    for _ in 0 .. count {
+        let target = rng.sample::<F, _>(StandardUniform) * total;
+        let mut mass = F::zero();
+        let mut j = 0;
+
+        while j + 1 < remaining.len() {
+            mass = mass + weights[remaining[j]];
+
+            if target < mass {
+                break;
+            }
+
+            j += 1;
+        }
+
+        let i = remaining.remove(j);
+        total = total - weights[i];
+        sampled.push(i);
+    }
+
+    sampled
+}
+
+fn exp_jumps<F, R>(
+    weights: &[F],
+    positive: &[usize],
+    count: usize,
+    rng: &mut R,
+) -> Vec<usize>
+where
+    F: Float,
+    R: RngCore,
+    Exp1: Distribution<F>,
+{
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let mut heap = BinaryHeap::with_capacity(count + 1);
+    let (head, tail) = positive.split_at(count);
+
+    for &i in head {
+        let random: F = Exp1.sample(rng);
+        heap.push(WsworEntry {
+            val: i,
+            index: i,
+            weight: random / weights[i],
+        });
+    }
+
+    // the items that beat the threshold key `t` are the ones containing a
+    // point of a Poisson process of rate `t` laid over the cumulative weights
+    let mut threshold = heap.peek().unwrap().weight;
+    let mut jump = Exp1.sample(rng) / threshold;
+
+    for &i in tail {
+        let weight = weights[i];
+
+        if weight <= jump {
+            jump = jump - weight;
+            continue;
+        }
+
+        heap.push(WsworEntry {
+            val: i,
+            index: i,
+            weight: uniform::truncated_exp1(weight * threshold, rng) / weight,
+        });
+        heap.pop();
+
+        threshold = heap.peek().unwrap().weight;
+        jump = Exp1.sample(rng) / threshold;
+    }
+
+    // ascending keys are the order of a successive draw
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|entry| entry.val)
+        .collect()
+}
+
+struct Bucket<F> {
+    items: Vec<usize>,
+    total: F,
+}
+
+fn bucket<F, R>(
+    weights: &[F],
+    positive: &[usize],
+    count: usize,
+    rng: &mut R,
+) -> Vec<usize>
+where
+    F: Float,
+    R: RngCore,
+    StandardUniform: Distribution<F>,
+{
+    // bucket `e` holds the weights in `[2^e, 2^(e + 1))`
+    let mut buckets = BTreeMap::new();
+
+    for &i in positive {
+        let exponent = binary_exponent(weights[i]);
+        let bucket = buckets.entry(exponent).or_insert(Bucket {
+            items: Vec::new(),
+            total: F::zero(),
+        });
+
+        bucket.items.push(i);
+        bucket.total = bucket.total + weights[i];
+    }
+
+    let two = F::one() + F::one();
+    let mut sampled = Vec::with_capacity(count);
+
+    while sampled.len() < count {
+        let total = buckets
+            .values()
+            .fold(F::zero(), |sum, bucket| sum + bucket.total);
+        let mut target = rng.sample::<F, _>(StandardUniform) * total;
+
+        // rounding can put the target past the last bucket, in which case the
+        // last one is used
+        let exponent = buckets
+            .iter()
+            .find_map(|(&exponent, bucket)| {
+                if target < bucket.total {
+                    return Some(exponent);
+                }
+
+                target = target - bucket.total;
+                None
+            })
+            .unwrap_or_else(|| *buckets.keys().next_back().unwrap());
+
+        let bucket = buckets.get_mut(&exponent).unwrap();
+
+        // weights are compared relative to the bucket's lower bound `2^e`,
+        // since its upper bound overflows for the largest weights; `2^e` is
+        // built in two halves so that it doesn't underflow for the smallest
+        // subnormals either
+        let lower = two.powi(exponent / 2) * two.powi(exponent - exponent / 2);
+
+        loop {
+            let j = rng.random_range(0 .. bucket.items.len());
+            let weight = weights[bucket.items[j]];
+
+            if rng.sample::<F, _>(StandardUniform) * two < weight / lower {
+                sampled.push(bucket.items.swap_remove(j));
+                bucket.total = bucket.total - weight;
+                break;
+            }
+        }
+
+        if bucket.items.is_empty() {
+            buckets.remove(&exponent);
+        }
+    }
+
+    sampled
+}
+
+/// The exponent `e` with `2^e <= weight < 2^(e + 1)` of a positive weight,
+/// read off its bits, since `log2` can round up to the next exponent.
+fn binary_exponent<F: Float>(weight: F) -> i32 {
+    let (mantissa, exponent, _) = weight.integer_decode();
+    exponent as i32 + 63 - mantissa.leading_zeros() as i32
+}
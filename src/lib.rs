@@ -2,26 +2,112 @@
 //
 // - Müller, K. (2016). Accelerating weighted random sampling without replacement. Arbeitsberichte Verkehrs- Und Raumplanung, 1141. https://www.research-collection.ethz.ch/mapping/view/pub:176429
 
-#[cfg(test)]
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("either the `std` or the `libm` feature is needed for floats");
+
+#[cfg(feature = "std")]
+mod aggregated;
+#[cfg(feature = "alloc")]
+mod alias;
+mod array;
+#[cfg(feature = "alloc")]
+mod budgeted;
+#[cfg(feature = "std")]
+mod capped;
+#[cfg(feature = "std")]
+mod dynamic;
+#[cfg(feature = "alloc")]
+mod hashed;
+#[cfg(feature = "alloc")]
+mod icws;
+#[cfg(feature = "alloc")]
+mod indices;
+#[cfg(feature = "alloc")]
+mod multi;
+mod poisson;
+#[cfg(feature = "alloc")]
+mod replicated;
+#[cfg(feature = "alloc")]
+mod reservoir;
+#[cfg(feature = "std")]
+mod stratified;
+#[cfg(all(test, feature = "std"))]
 mod tests;
+#[cfg(feature = "alloc")]
+mod uniform;
+mod weight;
 
 use core::{
     cmp::Ordering,
-    num::FpCategory::*,
-};
-use std::{
-    collections::BinaryHeap,
-    error::Error,
     fmt::Display,
+    num::FpCategory::*,
 };
+#[cfg(feature = "std")]
+use std::error::Error;
 
 use num::Float;
 use rand::{
     distr::Distribution,
     RngCore,
 };
+#[cfg(feature = "alloc")]
+use rand::{
+    distr::OpenClosed01,
+    Rng,
+    SeedableRng,
+};
+#[cfg(feature = "alloc")]
+use rand_chacha::ChaCha8Rng;
 use rand_distr::Exp1;
 
+#[cfg(feature = "alloc")]
+use crate::reservoir::Reservoir;
+#[cfg(feature = "std")]
+pub use crate::{
+    aggregated::AggregatedWswor,
+    capped::CategoryCappedWswor,
+    dynamic::DynamicWeightedSet,
+    stratified::{
+        AllocatedWswor,
+        Allocation,
+        StrataOverflow,
+        StratifiedWswor,
+    },
+};
+#[cfg(feature = "alloc")]
+pub use crate::{
+    alias::AliasTable,
+    budgeted::BudgetedWswor,
+    hashed::HashedId,
+    icws::IcwsSketch,
+    indices::{
+        sample_indices,
+        IndexAlgorithm,
+    },
+    multi::{
+        MultiObjectiveItem,
+        MultiObjectiveWswor,
+    },
+    replicated::ReplicatedWswor,
+    reservoir::Backend,
+    uniform::UniformReservoir,
+};
+pub use crate::{
+    array::ArrayWswor,
+    poisson::{
+        Inclusion,
+        PoissonSampler,
+    },
+    weight::Weight,
+};
+
 #[derive(Debug)]
 pub enum HasInvalidWeights {
     NaN,
@@ -32,8 +118,8 @@ pub enum HasInvalidWeights {
 impl Display for HasInvalidWeights {
     fn fmt(
         &self,
-        f: &mut std::fmt::Formatter<'_>,
-    ) -> std::fmt::Result {
+        f: &mut core::fmt::Formatter<'_>,
+    ) -> core::fmt::Result {
         write!(
             f,
             "Cannot sample over values with {} weights.",
@@ -46,6 +132,7 @@ impl Display for HasInvalidWeights {
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for HasInvalidWeights {}
 
 impl HasInvalidWeights {
@@ -68,6 +155,8 @@ impl HasInvalidWeights {
 
 struct WsworEntry<F: Float, T> {
     weight: F,
+    // position of the item in the stream it was fed from
+    index: usize,
     val: T,
 }
 
@@ -76,7 +165,7 @@ impl<F: Float, T> PartialOrd for WsworEntry<F, T> {
         &self,
         other: &Self,
     ) -> Option<Ordering> {
-        self.weight.partial_cmp(&other.weight)
+        Some(self.cmp(other))
     }
 }
 
@@ -88,7 +177,7 @@ where
         &self,
         other: &Self,
     ) -> bool {
-        self.weight.eq(&other.weight)
+        self.rank(other).is_eq()
     }
 }
 
@@ -100,35 +189,191 @@ impl<F: Float, T> Ord for WsworEntry<F, T> {
         &self,
         other: &Self,
     ) -> Ordering {
-        self.weight.partial_cmp(&other.weight).unwrap()
+        self.rank(other)
     }
 }
 
+impl<F: Float, T> WsworEntry<F, T> {
+    /// The key of the entry, without its value.
+    #[cfg(feature = "alloc")]
+    fn key(&self) -> WsworEntry<F, ()> {
+        WsworEntry {
+            weight: self.weight,
+            index: self.index,
+            val: (),
+        }
+    }
+
+    /// Compares the keys of two entries, whatever their values.
+    fn rank<U>(
+        &self,
+        other: &WsworEntry<F, U>,
+    ) -> Ordering {
+        // NaN keys, which no valid weight should give, go last instead of
+        // panicking, and ties go to the item that came first, so they don't
+        // depend on the layout of the heap
+        self.weight
+            .partial_cmp(&other.weight)
+            .unwrap_or_else(|| self.weight.is_nan().cmp(&other.weight.is_nan()))
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+/// Turns an `Exp1` variate into the key of an item with the given weight: the
+/// variate over the weight, or the largest key for zero weights. Zero weights
+/// still take a variate so that every item consumes the same randomness.
+fn scale_key<F: Float>(
+    random: F,
+    weight: F,
+) -> F {
+    if weight == F::zero() {
+        F::max_value()
+    }
+    else {
+        random / weight
+    }
+}
+
+/// Progress of the constant-weight fast path of [`StreamingWswor`].
+#[cfg(feature = "alloc")]
+enum ConstantWeight<F> {
+    /// The fast path is not in use, either because it was never requested or
+    /// because the weights turned out to differ.
+    Off,
+    /// The fast path was requested but nothing has been fed yet.
+    Pending,
+    /// Every item fed so far had this positive weight. Also holds the number
+    /// of items left to skip, if one has been drawn.
+    Seen(F, Option<usize>),
+}
+
 /// One-pass Weighted Random Sampler Without Replacement.
 ///
-/// Can sample any number of elements.
-pub struct StreamingWswor<F: Float, T> {
+/// Can sample any number of elements, by any [`Weight`].
+///
+/// # Reproducibility
+///
+/// For a given stream and RNG state, the sample is the same across releases
+/// with the same major version, as are the orders given by
+/// [`take_in_stream_order`](StreamingWswor::take_in_stream_order) and
+/// [`take_in_draw_order`](StreamingWswor::take_in_draw_order). This assumes
+/// an RNG with a fixed algorithm, like `ChaCha8Rng` rather than `StdRng`,
+/// whose algorithm may change between `rand` releases, and that `rand_distr`
+/// keeps sampling `Exp1` the same way. The order of
+/// [`iter`](StreamingWswor::iter) and [`take`](StreamingWswor::take) is
+/// unspecified. Items with equal keys, like zero-weight items, are ranked by
+/// the order they were fed in, earlier items first.
+///
+/// Every call to [`feed`](StreamingWswor::feed) draws exactly one `Exp1`
+/// variate from the RNG, whatever the weight or the sample size, so the
+/// randomness an item gets only depends on its position in the stream. Calls
+/// that return an error draw nothing. The exception is the constant-weight
+/// fast path turned on by
+/// [`detect_constant_weight`](StreamingWswor::detect_constant_weight), which
+/// draws a skip length and a truncated key for each item it lets in, and
+/// nothing for the items it skips over. Batch feeding has its own contract,
+/// see [`feed_batch`](StreamingWswor::feed_batch).
+///
+/// [`log_keys`](StreamingWswor::log_keys) changes how keys are represented,
+/// not the variates they are made from, so up to rounding it keeps the sample
+/// the same as long as the keys don't overflow.
+#[cfg(feature = "alloc")]
+pub struct StreamingWswor<W: Weight, T> {
     count: usize,
-    heap: BinaryHeap<WsworEntry<F, T>>,
+    seen: usize,
+    reservoir: Reservoir<W::Key, T>,
+    constant: ConstantWeight<W>,
+    // key of the counter-based generator used by `feed_at`
+    counter_key: Option<[u8; 32]>,
+    // keys are `ln(E) - ln(w)` rather than `E / w`
+    log_keys: bool,
 }
 
-impl<F, T> StreamingWswor<F, T>
+#[cfg(feature = "alloc")]
+impl<W, T> StreamingWswor<W, T>
 where
-    F: Float,
-    Exp1: Distribution<F>,
+    W: Weight,
+    Exp1: Distribution<W::Key>,
 {
-    pub fn new(count: usize) -> StreamingWswor<F, T> {
+    pub fn new(count: usize) -> StreamingWswor<W, T> {
+        StreamingWswor::with_capacity(count, count.saturating_add(1))
+    }
+
+    /// A sampler of `count` items that only reserves room for `capacity` up
+    /// front, for when many samplers share a budget that few of them fill.
+    pub(crate) fn with_capacity(
+        count: usize,
+        capacity: usize,
+    ) -> StreamingWswor<W, T> {
         StreamingWswor {
             count,
-            heap: BinaryHeap::with_capacity(count + 1),
+            seen: 0,
+            reservoir: Reservoir::with_capacity(Backend::Heap, count, capacity),
+            constant: ConstantWeight::Off,
+            counter_key: None,
+            log_keys: false,
+        }
+    }
+
+    /// Watches for a stream where every item has the same weight and, while
+    /// that holds, skips over items the same way [`UniformReservoir`] does
+    /// instead of drawing a key for each of them.
+    ///
+    /// The first item that has a different weight turns the fast path off for
+    /// good; the items sampled until then are kept as they are. Has no effect
+    /// if items have already been fed.
+    pub fn detect_constant_weight(mut self) -> StreamingWswor<W, T> {
+        if self.reservoir.is_empty() {
+            self.constant = ConstantWeight::Pending;
+        }
+
+        self
+    }
+
+    /// Picks how the sample is stored, see [`Backend`]. Has no effect if items
+    /// have already been fed.
+    pub fn backend(
+        mut self,
+        backend: Backend,
+    ) -> StreamingWswor<W, T> {
+        if self.reservoir.is_empty() {
+            self.reservoir = Reservoir::new(backend, self.count);
+        }
+
+        self
+    }
+
+    /// Switches to counter-based randomness for
+    /// [`feed_at`](StreamingWswor::feed_at): the variate of the item at index
+    /// `i` comes from a ChaCha8 generator keyed by `seed`, on stream `i`.
+    pub fn counter_based(
+        mut self,
+        seed: u64,
+    ) -> StreamingWswor<W, T> {
+        self.counter_key = Some(ChaCha8Rng::seed_from_u64(seed).get_seed());
+        self
+    }
+
+    /// Represents keys by their logarithm, `ln(E) - ln(w)`, instead of
+    /// `E / w`, for weights spanning so many orders of magnitude that the
+    /// quotient would overflow to infinity or underflow to zero, losing the
+    /// order between keys. Has no effect if items have already been fed.
+    ///
+    /// [`threshold_weight`](StreamingWswor::threshold_weight) then gives the
+    /// logarithm of the threshold key.
+    pub fn log_keys(mut self) -> StreamingWswor<W, T> {
+        if self.reservoir.is_empty() {
+            self.log_keys = true;
         }
+
+        self
     }
 
     /// NOTE: the consumption of the iterator will be halted prematurely if an
     /// invalid weight is detected
     pub fn feed_iter<R: RngCore>(
         &mut self,
-        iter: impl Iterator<Item = (F, T)>,
+        iter: impl Iterator<Item = (W, T)>,
         rng: &mut R,
     ) -> Result<(), HasInvalidWeights> {
         for (w, v) in iter {
@@ -141,97 +386,469 @@ where
     pub fn feed<R: RngCore>(
         &mut self,
         val: T,
-        weight: F,
+        weight: W,
         rng: &mut R,
     ) -> Result<bool, HasInvalidWeights> {
-        HasInvalidWeights::check_weight(&weight)?;
+        weight.validate()?;
 
-        let mut dist = Exp1.sample_iter(rng);
+        if self.is_constant_weight(weight) && self.count != 0 && self.is_full()
+        {
+            let index = self.next_index();
+            return Ok(self.feed_skipping(val, index, weight, rng));
+        }
 
+        let random: W::Key = Exp1.sample(rng);
         let entry = WsworEntry {
             val,
-            weight: {
-                if weight == F::zero() {
-                    F::max_value()
+            index: self.next_index(),
+            weight: self.key(random, weight),
+        };
+
+        Ok(self.push_entry(entry))
+    }
+
+    /// Feeds `vals` with the matching `weights`, cloning only the items that
+    /// make it into the sample. Returns how many did.
+    ///
+    /// See [`feed_batch`](StreamingWswor::feed_batch) for how the keys are
+    /// drawn.
+    ///
+    /// # Panics
+    ///
+    /// If the slices differ in length.
+    pub fn feed_slice<R: RngCore>(
+        &mut self,
+        weights: &[W],
+        vals: &[T],
+        rng: &mut R,
+    ) -> Result<usize, HasInvalidWeights>
+    where
+        T: Clone,
+        OpenClosed01: Distribution<W::Key>,
+    {
+        assert_eq!(weights.len(), vals.len(), "one weight per value");
+        self.feed_chunks(weights, vals.iter(), T::clone, rng)
+    }
+
+    /// Feeds the values of `vals` with the matching `weights`, returning how
+    /// many made it into the sample. Values past the last weight are not
+    /// consumed, and weights past the last value are ignored.
+    ///
+    /// Keys are computed a chunk at a time: a batch of uniform variates is
+    /// drawn first and turned into exponential variates with `-ln(u)` in a
+    /// loop the compiler can vectorize, and only the keys that beat the
+    /// current threshold get to touch the reservoir. Values that don't make
+    /// it are skipped over with [`Iterator::nth`].
+    ///
+    /// Every weight is checked before anything is fed, so on an error nothing
+    /// is. Like [`feed`](StreamingWswor::feed), every item draws exactly one
+    /// variate, but a uniform one from
+    /// [`OpenClosed01`](rand::distr::OpenClosed01) rather than an `Exp1`, so a
+    /// batch doesn't give the same sample as feeding its items one by one.
+    /// While the constant-weight fast path is on, items are fed one by one.
+    pub fn feed_batch<R: RngCore>(
+        &mut self,
+        weights: &[W],
+        vals: impl Iterator<Item = T>,
+        rng: &mut R,
+    ) -> Result<usize, HasInvalidWeights>
+    where
+        OpenClosed01: Distribution<W::Key>,
+    {
+        self.feed_chunks(weights, vals, |val| val, rng)
+    }
+
+    /// Does the work of [`feed_batch`](StreamingWswor::feed_batch), turning
+    /// the values that are kept into items with `to_item`.
+    fn feed_chunks<V, R: RngCore>(
+        &mut self,
+        weights: &[W],
+        mut vals: impl Iterator<Item = V>,
+        to_item: impl Fn(V) -> T,
+        rng: &mut R,
+    ) -> Result<usize, HasInvalidWeights>
+    where
+        OpenClosed01: Distribution<W::Key>,
+    {
+        for weight in weights {
+            weight.validate()?;
+        }
+
+        let mut kept = 0;
+
+        if !matches!(self.constant, ConstantWeight::Off) {
+            for (&weight, val) in weights.iter().zip(vals) {
+                kept += self.feed(to_item(val), weight, rng)? as usize;
+            }
+
+            return Ok(kept);
+        }
+
+        const CHUNK: usize = 64;
+        let mut keys = [num::zero(); CHUNK];
+
+        for chunk in weights.chunks(CHUNK) {
+            let keys = &mut keys[.. chunk.len()];
+
+            for key in keys.iter_mut() {
+                *key = rng.sample(OpenClosed01);
+            }
+            for (key, &weight) in keys.iter_mut().zip(chunk) {
+                *key = self.key(-key.ln(), weight);
+            }
+
+            // the threshold only goes down while the chunk is fed, so anything
+            // that doesn't beat it now won't later either
+            let threshold = match self.reservoir.bound() {
+                _ if self.count == 0 => W::Key::neg_infinity(),
+                Some(top) if self.is_full() => top,
+                _ => W::Key::max_value(),
+            };
+
+            // values consumed from `vals` so far in this chunk
+            let mut consumed = 0;
+
+            for (i, &key) in keys.iter().enumerate() {
+                if threshold < key {
+                    continue;
                 }
+
+                let Some(val) = vals.nth(i - consumed)
                 else {
-                    let random: F = dist.next().unwrap();
-                    random / weight
+                    self.seen += i;
+                    return Ok(kept);
+                };
+
+                consumed = i + 1;
+
+                // the threshold may have gone down since, and turning the
+                // value into an item may not be free
+                if self.is_full()
+                    && self.reservoir.bound().is_some_and(|top| top <= key)
+                {
+                    continue;
                 }
-            },
+
+                kept += self.push_entry(WsworEntry {
+                    val: to_item(val),
+                    index: self.seen + i,
+                    weight: key,
+                }) as usize;
+            }
+
+            // the rest of the chunk's values didn't make it
+            if consumed < chunk.len() {
+                vals.nth(chunk.len() - consumed - 1);
+            }
+
+            self.seen += chunk.len();
+        }
+
+        Ok(kept)
+    }
+
+    /// Feeds an item whose key is derived from a hash of `id` and `seed`
+    /// instead of from an RNG.
+    ///
+    /// Samplers fed with the same seed give every ID the same exponential
+    /// variate, so their samples are coordinated: an ID that is sampled from
+    /// one stream is likely to be sampled from another where it has a similar
+    /// weight, and identical streams give identical samples regardless of the
+    /// order they are fed in. This makes the sampler a bottom-k sketch of the
+    /// IDs. IDs are hashed through [`HashedId`], so an ID gets the same
+    /// variate on every platform and Rust release.
+    ///
+    /// NOTE: this turns off the constant-weight fast path
+    pub fn feed_hashed<I: HashedId + ?Sized>(
+        &mut self,
+        val: T,
+        id: &I,
+        weight: W,
+        seed: u64,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        self.constant = ConstantWeight::Off;
+
+        let entry = WsworEntry {
+            val,
+            index: self.next_index(),
+            weight: self.key(hashed::hashed_exp1(id, seed), weight),
         };
 
+        Ok(self.push_entry(entry))
+    }
+
+    /// Feeds the item at `index` in the dataset, with randomness that only
+    /// depends on the seed and the index.
+    ///
+    /// Items can thus be fed in any order, or split into shards that are
+    /// sampled separately and then [`merge`](StreamingWswor::merge)d, and the
+    /// sample comes out the same as feeding them all in order. Indices are
+    /// also what ties are broken by, so they should be unique, and calls
+    /// shouldn't be mixed with [`feed`](StreamingWswor::feed).
+    ///
+    /// NOTE: this turns off the constant-weight fast path
+    ///
+    /// # Panics
+    ///
+    /// If the sampler wasn't made
+    /// [`counter_based`](StreamingWswor::counter_based).
+    pub fn feed_at(
+        &mut self,
+        index: usize,
+        val: T,
+        weight: W,
+    ) -> Result<bool, HasInvalidWeights> {
+        let key = self
+            .counter_key
+            .expect("feed_at needs a counter_based sampler");
+
+        weight.validate()?;
+
+        self.constant = ConstantWeight::Off;
+        self.seen = self.seen.max(index.saturating_add(1));
+
+        let mut rng = ChaCha8Rng::from_seed(key);
+        rng.set_stream(index as u64);
+        let random: W::Key = Exp1.sample(&mut rng);
+
+        let entry = WsworEntry {
+            val,
+            index,
+            weight: self.key(random, weight),
+        };
+
+        Ok(self.push_entry(entry))
+    }
+
+    /// Adds the sample of another sampler fed with a disjoint part of the
+    /// stream, leaving the sample of the whole stream.
+    ///
+    /// With [`feed_at`](StreamingWswor::feed_at) and the same seed on both
+    /// sides, the result is identical to feeding everything into one sampler.
+    ///
+    /// NOTE: this turns off the constant-weight fast path
+    ///
+    /// # Panics
+    ///
+    /// If only one of the samplers uses
+    /// [`log_keys`](StreamingWswor::log_keys).
+    pub fn merge(
+        &mut self,
+        other: StreamingWswor<W, T>,
+    ) {
+        assert_eq!(
+            self.log_keys, other.log_keys,
+            "merged samplers must agree on log_keys"
+        );
+
+        self.constant = ConstantWeight::Off;
+        self.seen = self.seen.max(other.seen);
+
+        for entry in other.reservoir.into_vec() {
+            self.push_entry(entry);
+        }
+    }
+
+    /// Turns an exponential variate into the key of an item with the given
+    /// weight, the largest key for zero weights.
+    fn key(
+        &self,
+        random: W::Key,
+        weight: W,
+    ) -> W::Key {
+        if self.log_keys && !weight.is_zero() {
+            random.ln() - weight.to_key().ln()
+        }
+        else {
+            scale_key(random, weight.to_key())
+        }
+    }
+
+    /// Hands out the stream index of the item being fed.
+    fn next_index(&mut self) -> usize {
+        self.seen += 1;
+        self.seen - 1
+    }
+
+    /// Inserts an entry into the reservoir, returning whether it was kept.
+    fn push_entry(
+        &mut self,
+        entry: WsworEntry<W::Key, T>,
+    ) -> bool {
         // if our sample size is zero, empty the reservoir, then exit
         // make sure that the rng state is modified for consistency
         if self.count == 0 {
-            while self.heap.pop().is_some() {}
+            self.reservoir.shrink_to(0);
 
-            return Ok(false);
+            return false;
         }
 
-        // a really fast guard so we don't have to push and then drop the same
-        // element in case what we're inserting won't really make it into the
-        // list
-        if self.count <= self.heap.len() {
-            if self.heap.peek().unwrap().weight < entry.weight {
-                return Ok(false);
-            }
+        self.reservoir.insert(entry, self.count)
+    }
+
+    /// Updates the constant-weight tracking with the weight of the item about
+    /// to be fed, returning whether the fast path applies to it.
+    fn is_constant_weight(
+        &mut self,
+        weight: W,
+    ) -> bool {
+        match self.constant {
+            ConstantWeight::Off => false,
+            ConstantWeight::Pending if !weight.is_zero() => {
+                self.constant = ConstantWeight::Seen(weight, None);
+                true
+            },
+            ConstantWeight::Seen(seen, _) if seen == weight => true,
+            _ => {
+                self.constant = ConstantWeight::Off;
+                false
+            },
         }
+    }
+
+    /// Feeds an item with the constant weight into a full reservoir.
+    ///
+    /// With a threshold key `t` and weight `w`, each item beats the threshold
+    /// with probability `1 - exp(-w * t)`, so the number of items to pass over
+    /// is geometric and the key of the item that does get in is an exponential
+    /// variate truncated below `w * t`.
+    fn feed_skipping<R: RngCore>(
+        &mut self,
+        val: T,
+        index: usize,
+        weight: W,
+        rng: &mut R,
+    ) -> bool {
+        let reservoir = &self.reservoir;
+        let log_keys = self.log_keys;
 
-        self.heap.push(entry);
+        // with log keys, `w * t` is `exp(ln(w) + ln(t))`, which is of the
+        // order of the sampling rate even if `w` and `t` are not
+        let rate = || {
+            let threshold = reservoir.bound().unwrap();
 
-        if self.count < self.heap.len() {
-            self.heap.pop();
+            if log_keys {
+                (weight.to_key().ln() + threshold).exp()
+            }
+            else {
+                weight.to_key() * threshold
+            }
+        };
+
+        let ConstantWeight::Seen(_, skip) = &mut self.constant
+        else {
+            unreachable!()
+        };
+
+        let remaining =
+            skip.get_or_insert_with(|| uniform::skip_length(rate(), rng));
+
+        if *remaining > 0 {
+            *remaining -= 1;
+            return false;
         }
 
-        Ok(true)
+        *skip = None;
+        let rate = rate();
+        let key = self.key(uniform::truncated_exp1(rate, rng), weight);
+        self.reservoir.insert(
+            WsworEntry {
+                val,
+                index,
+                weight: key,
+            },
+            self.count,
+        )
+    }
+
+    /// Lowers the sample size to `count`, dropping the sampled items with the
+    /// largest keys. What is left is exactly what a sampler created with the
+    /// lower sample size would have kept.
+    pub fn shrink_to(
+        &mut self,
+        count: usize,
+    ) {
+        self.count = self.count.min(count);
+
+        self.reservoir.shrink_to(self.count);
+
+        // the threshold may have moved, so any pending skip no longer applies
+        if let ConstantWeight::Seen(_, skip) = &mut self.constant {
+            *skip = None;
+        }
     }
 
     pub fn is_full(&self) -> bool {
-        self.count <= self.heap.len()
+        self.count <= self.reservoir.len()
     }
 
-    pub fn threshold_weight(&self) -> Option<F> {
-        self.heap.peek().map(|entry| entry.weight)
+    pub fn len(&self) -> usize {
+        self.reservoir.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reservoir.is_empty()
+    }
+
+    pub fn threshold_weight(&self) -> Option<W::Key> {
+        self.reservoir.peek()
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        self.heap.iter().map(|entry| &entry.val)
+        self.reservoir.iter()
     }
 
     pub fn take(self) -> impl Iterator<Item = T> {
-        self.heap.into_iter().map(|entry| entry.val)
+        self.reservoir.into_vec().into_iter().map(|entry| entry.val)
+    }
+
+    /// The sampled items in the order they were fed.
+    pub fn take_in_stream_order(self) -> impl Iterator<Item = T> {
+        let mut entries = self.reservoir.into_vec();
+        entries.sort_unstable_by_key(|entry| entry.index);
+        entries.into_iter().map(|entry| entry.val)
+    }
+
+    /// The sampled items by increasing key, which is the order drawing them
+    /// one at a time with probability proportional to their weights, without
+    /// replacement, would give.
+    pub fn take_in_draw_order(self) -> impl Iterator<Item = T> {
+        let mut entries = self.reservoir.into_vec();
+        entries.sort_unstable();
+        entries.into_iter().map(|entry| entry.val)
     }
 }
 
 /// Special case for the One-pass Weighted Sampler where you just need one item
 /// sampled.
-pub struct SingleStreamingWs<F: Float, T> {
+pub struct SingleStreamingWs<W: Weight, T> {
     value: Option<T>,
-    exp_value_weight: F,
+    exp_value_weight: W::Key,
 }
 
-impl<F, T> SingleStreamingWs<F, T>
+impl<W, T> SingleStreamingWs<W, T>
 where
-    F: Float,
-    Exp1: Distribution<F>,
+    W: Weight,
+    Exp1: Distribution<W::Key>,
 {
-    pub fn new() -> SingleStreamingWs<F, T> {
+    pub fn new() -> SingleStreamingWs<W, T> {
         SingleStreamingWs {
             value: None,
-            exp_value_weight: F::zero(),
+            exp_value_weight: num::zero(),
         }
     }
 
     pub fn feed<R: RngCore>(
         &mut self,
         val: T,
-        weight: F,
+        weight: W,
         rng: &mut R,
     ) -> Result<(), HasInvalidWeights> {
-        HasInvalidWeights::check_weight(&weight)?;
+        weight.validate()?;
         let mut dist = Exp1.sample_iter(rng);
-        let exp_weight = dist.next().unwrap() / weight;
+        let exp_weight = dist.next().unwrap() / weight.to_key();
 
         if self.value.is_none() {
             self.value = Some(val);
@@ -247,7 +864,7 @@ where
 
     pub fn feed_iter<R: RngCore>(
         &mut self,
-        iter: impl Iterator<Item = (F, T)>,
+        iter: impl Iterator<Item = (W, T)>,
         rng: &mut R,
     ) -> Result<(), HasInvalidWeights> {
         for (w, v) in iter {
@@ -266,16 +883,27 @@ where
     }
 }
 
+impl<W, T> Default for SingleStreamingWs<W, T>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Quick and easy weighted random sampling without replacement.
-pub fn wswor<F: Float, T, R: RngCore>(
-    iter: impl Iterator<Item = (F, T)>,
+#[cfg(feature = "alloc")]
+pub fn wswor<W, T, R>(
+    iter: impl Iterator<Item = (W, T)>,
     rng: &mut R,
     count: usize,
 ) -> Result<impl Iterator<Item = T>, HasInvalidWeights>
 where
-    F: Float,
+    W: Weight,
     R: RngCore,
-    Exp1: Distribution<F>,
+    Exp1: Distribution<W::Key>,
 {
     let mut heap = StreamingWswor::new(count);
     heap.feed_iter(iter, rng)?;
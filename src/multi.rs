@@ -0,0 +1,267 @@
+// Based on multi-objective weighted sampling with a shared random per item.
+//
+// - Cohen, E. (2015). Multi-objective weighted sampling. 2015 Third IEEE
+//   Workshop on Hot Topics in Web Systems and Technologies, 13–18.
+
+use alloc::{
+    collections::BinaryHeap,
+    vec,
+    vec::Vec,
+};
+
+use num::{
+    Float,
+    One,
+    Zero,
+};
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    scale_key,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// An item of the sample taken by [`MultiObjectiveWswor`], along with the
+/// probabilities it was included with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiObjectiveItem<W: Weight, T> {
+    pub val: T,
+    pub weights: Vec<W>,
+    /// Probability of being in the bottom-k set of each objective.
+    pub probabilities: Vec<W::Key>,
+    /// Probability of being in the sample at all, which is what sums under
+    /// any of the objectives should be estimated with.
+    pub probability: W::Key,
+}
+
+struct Slot<W, T> {
+    val: T,
+    weights: Vec<W>,
+    // number of objectives whose bottom-k set holds the item
+    refs: usize,
+}
+
+/// One-pass Weighted Random Sampler Without Replacement for several weight
+/// functions at once.
+///
+/// Every item draws a single `Exp1` variate and gets the key `Exp1 / weight`
+/// under each objective. The sample is the union of the `count` smallest keys
+/// of every objective, so it is a sample of `count` items for each of them
+/// while items that do well under several objectives are only stored once.
+pub struct MultiObjectiveWswor<W: Weight, T> {
+    count: usize,
+    seen: usize,
+    heaps: Vec<BinaryHeap<WsworEntry<W::Key, usize>>>,
+    // smallest key left out of each objective's bottom-k set
+    thresholds: Vec<Option<W::Key>>,
+    slots: Vec<Option<Slot<W, T>>>,
+    free: Vec<usize>,
+    len: usize,
+}
+
+impl<W, T> MultiObjectiveWswor<W, T>
+where
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new(
+        count: usize,
+        objectives: usize,
+    ) -> MultiObjectiveWswor<W, T> {
+        MultiObjectiveWswor {
+            count,
+            seen: 0,
+            heaps: (0 .. objectives).map(|_| BinaryHeap::new()).collect(),
+            thresholds: vec![None; objectives],
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn objectives(&self) -> usize {
+        self.heaps.len()
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (Vec<W>, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, v) in iter {
+            self.feed(v, &w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds an item with one weight per objective, returning whether it made
+    /// it into the sample.
+    ///
+    /// # Panics
+    ///
+    /// If the number of weights differs from the number of objectives.
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        weights: &[W],
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        assert_eq!(
+            weights.len(),
+            self.objectives(),
+            "one weight per objective"
+        );
+
+        for weight in weights {
+            weight.validate()?;
+        }
+
+        let random: W::Key = Exp1.sample(rng);
+        let position = self.seen;
+        let mut val = Some(val);
+        self.seen += 1;
+        let mut slot = None;
+
+        for (objective, &weight) in weights.iter().enumerate() {
+            let key = scale_key(random, weight.to_key());
+
+            let heap = &self.heaps[objective];
+            let fits = heap.len() < self.count;
+            let beats = heap.peek().is_some_and(|top| key < top.weight);
+
+            if !fits && !beats {
+                self.leave_out(objective, key);
+                continue;
+            }
+
+            if !fits {
+                let evicted = self.heaps[objective].pop().unwrap();
+                self.leave_out(objective, evicted.weight);
+                self.release(evicted.val);
+            }
+
+            let index = *slot.get_or_insert_with(|| {
+                self.store(val.take().unwrap(), weights.to_vec())
+            });
+
+            self.slots[index].as_mut().unwrap().refs += 1;
+            self.heaps[objective].push(WsworEntry {
+                weight: key,
+                index: position,
+                val: index,
+            });
+        }
+
+        Ok(slot.is_some())
+    }
+
+    fn leave_out(
+        &mut self,
+        objective: usize,
+        key: W::Key,
+    ) {
+        let threshold = &mut self.thresholds[objective];
+        *threshold =
+            Some(threshold.map_or(key, |threshold| threshold.min(key)));
+    }
+
+    fn store(
+        &mut self,
+        val: T,
+        weights: Vec<W>,
+    ) -> usize {
+        let slot = Some(Slot {
+            val,
+            weights,
+            refs: 0,
+        });
+
+        self.len += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = slot;
+                index
+            },
+            None => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            },
+        }
+    }
+
+    fn release(
+        &mut self,
+        index: usize,
+    ) {
+        let slot = self.slots[index].as_mut().unwrap();
+        slot.refs -= 1;
+
+        if slot.refs == 0 {
+            self.slots[index] = None;
+            self.free.push(index);
+            self.len -= 1;
+        }
+    }
+
+    /// Number of distinct items in the sample.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The smallest key left out of the bottom-k set of an objective.
+    pub fn threshold_weight(
+        &self,
+        objective: usize,
+    ) -> Option<W::Key> {
+        self.thresholds[objective]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().flatten().map(|slot| &slot.val)
+    }
+
+    /// The sampled items with their inclusion probabilities, which are
+    /// `1 - exp(-weight * threshold)` for each objective, or one for the
+    /// objectives that never left anything out.
+    pub fn take(self) -> impl Iterator<Item = MultiObjectiveItem<W, T>> {
+        let thresholds = self.thresholds;
+
+        self.slots.into_iter().flatten().map(move |slot| {
+            let probabilities: Vec<W::Key> = slot
+                .weights
+                .iter()
+                .zip(&thresholds)
+                .map(|(weight, threshold)| match threshold {
+                    Some(threshold) => {
+                        W::Key::one() - (-weight.to_key() * *threshold).exp()
+                    },
+                    None => W::Key::one(),
+                })
+                .collect();
+
+            MultiObjectiveItem {
+                val: slot.val,
+                weights: slot.weights,
+                probability: probabilities
+                    .iter()
+                    .copied()
+                    .fold(W::Key::zero(), Float::max),
+                probabilities,
+            }
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use num::Float;
+use rand::{
+    distr::{
+        Distribution,
+        StandardUniform,
+    },
+    Rng,
+    RngCore,
+};
+
+use crate::HasInvalidWeights;
+
+/// Decision made by [`PoissonSampler::feed`] for a single item.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inclusion<F> {
+    pub included: bool,
+    /// Probability with which the item was included, for Horvitz-Thompson
+    /// style estimates.
+    pub probability: F,
+}
+
+enum Rate<F> {
+    Fixed(F),
+    /// Rate is recomputed from the running total weight so that the expected
+    /// sample size is this target.
+    Adaptive(F),
+}
+
+/// Weighted Poisson Sampler.
+///
+/// Includes every item independently with probability `min(1, weight * rate)`
+/// and decides on the spot, so nothing has to be buffered. The sample size is
+/// random; its expectation is the sum of the inclusion probabilities.
+pub struct PoissonSampler<F> {
+    rate: Rate<F>,
+    total: F,
+    expected: F,
+    sampled: usize,
+}
+
+impl<F> PoissonSampler<F>
+where
+    F: Float,
+    StandardUniform: Distribution<F>,
+{
+    pub fn new(rate: F) -> Result<PoissonSampler<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&rate)?;
+        Ok(Self::with_rate(Rate::Fixed(rate)))
+    }
+
+    /// Creates a sampler whose expected sample size is `count` for a stream
+    /// with the given total weight.
+    pub fn with_expected_size(
+        count: F,
+        total_weight: F,
+    ) -> Result<PoissonSampler<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&total_weight)?;
+        Self::new(count / total_weight)
+    }
+
+    /// Creates a sampler that targets an expected sample size of `count`
+    /// without knowing the total weight in advance.
+    ///
+    /// Before every item, the rate is set to `count` over the total weight seen
+    /// so far, that item included, which is the rate the item would get if the
+    /// stream ended with it. Earlier items thus get higher probabilities than
+    /// the final total would have given them, so the expected sample size
+    /// overshoots `count`, by a term that grows with the logarithm of the
+    /// ratio between the final total weight and the weight of the first item.
+    pub fn adaptive(count: F) -> Result<PoissonSampler<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&count)?;
+        Ok(Self::with_rate(Rate::Adaptive(count)))
+    }
+
+    fn with_rate(rate: Rate<F>) -> PoissonSampler<F> {
+        PoissonSampler {
+            rate,
+            total: F::zero(),
+            expected: F::zero(),
+            sampled: 0,
+        }
+    }
+
+    /// Switches to a fixed rate from now on.
+    pub fn set_rate(
+        &mut self,
+        rate: F,
+    ) -> Result<(), HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&rate)?;
+        self.rate = Rate::Fixed(rate);
+        Ok(())
+    }
+
+    /// The rate the last item was sampled with, or that the next one will be
+    /// if it is fixed.
+    pub fn rate(&self) -> F {
+        match self.rate {
+            Rate::Fixed(rate) => rate,
+            Rate::Adaptive(_) if self.total == F::zero() => F::infinity(),
+            Rate::Adaptive(count) => count / self.total,
+        }
+    }
+
+    /// Decides whether to include an item with the given weight.
+    ///
+    /// Randomness is only drawn when the inclusion probability is strictly
+    /// between zero and one.
+    pub fn feed<R: RngCore>(
+        &mut self,
+        weight: F,
+        rng: &mut R,
+    ) -> Result<Inclusion<F>, HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&weight)?;
+
+        self.total = self.total + weight;
+
+        let probability = match weight * self.rate() {
+            p if p.is_nan() => F::zero(),
+            p => p.min(F::one()),
+        };
+
+        let included = if probability == F::zero() {
+            false
+        }
+        else if probability == F::one() {
+            true
+        }
+        else {
+            rng.sample::<F, _>(StandardUniform) < probability
+        };
+
+        self.expected = self.expected + probability;
+        self.sampled += included as usize;
+
+        Ok(Inclusion {
+            included,
+            probability,
+        })
+    }
+
+    /// Total weight of the items fed so far.
+    pub fn total_weight(&self) -> F {
+        self.total
+    }
+
+    /// Expected number of items included so far, given the probabilities they
+    /// were sampled with.
+    pub fn expected_size(&self) -> F {
+        self.expected
+    }
+
+    /// Number of items included so far.
+    pub fn sampled(&self) -> usize {
+        self.sampled
+    }
+}
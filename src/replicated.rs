@@ -0,0 +1,142 @@
+use alloc::{
+    collections::BinaryHeap,
+    rc::Rc,
+    vec::Vec,
+};
+
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    scale_key,
+    HasInvalidWeights,
+    Weight,
+    WsworEntry,
+};
+
+/// Several independent One-pass Weighted Random Samplers Without Replacement
+/// fed from the same stream, e.g. for bootstrap confidence intervals.
+///
+/// Every replicate is a sample of `count` items drawn with its own keys, but
+/// an item kept by several replicates is only stored once and shared between
+/// them, and only cloned when the samples are taken out.
+pub struct ReplicatedWswor<W: Weight, T> {
+    count: usize,
+    seen: usize,
+    heaps: Vec<BinaryHeap<WsworEntry<W::Key, Rc<T>>>>,
+    // buffer for the keys of the item being fed, one per replicate
+    keys: Vec<W::Key>,
+}
+
+impl<W, T> ReplicatedWswor<W, T>
+where
+    W: Weight,
+    T: Clone,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new(
+        count: usize,
+        replicates: usize,
+    ) -> ReplicatedWswor<W, T> {
+        ReplicatedWswor {
+            count,
+            seen: 0,
+            heaps: (0 .. replicates).map(|_| BinaryHeap::new()).collect(),
+            keys: Vec::with_capacity(replicates),
+        }
+    }
+
+    pub fn replicates(&self) -> usize {
+        self.heaps.len()
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (W, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (w, v) in iter {
+            self.feed(v, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds an item to every replicate, returning whether any of them kept
+    /// it.
+    ///
+    /// The keys for all the replicates are drawn in one go before any of them
+    /// is looked at.
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        let index = self.seen;
+        self.seen += 1;
+        self.keys.clear();
+
+        self.keys.extend(
+            Exp1.sample_iter(&mut *rng)
+                .take(self.replicates())
+                .map(|random| scale_key(random, weight.to_key())),
+        );
+
+        if self.count == 0 {
+            return Ok(false);
+        }
+
+        let mut shared: Option<Rc<T>> = None;
+        let mut val = Some(val);
+
+        for (heap, &key) in self.heaps.iter_mut().zip(&self.keys) {
+            let full = self.count <= heap.len();
+
+            if full && heap.peek().is_some_and(|top| top.weight <= key) {
+                continue;
+            }
+
+            let shared =
+                shared.get_or_insert_with(|| Rc::new(val.take().unwrap()));
+
+            heap.push(WsworEntry {
+                weight: key,
+                index,
+                val: Rc::clone(shared),
+            });
+
+            if full {
+                heap.pop();
+            }
+        }
+
+        Ok(shared.is_some())
+    }
+
+    /// The items of each replicate.
+    pub fn iter(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        self.heaps
+            .iter()
+            .map(|heap| heap.iter().map(|entry| entry.val.as_ref()))
+    }
+
+    /// One vector of items per replicate.
+    pub fn take(self) -> Vec<Vec<T>> {
+        self.heaps
+            .into_iter()
+            .map(|heap| {
+                heap.into_iter()
+                    .map(|entry| Rc::unwrap_or_clone(entry.val))
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,314 @@
+use alloc::{
+    collections::BinaryHeap,
+    vec::Vec,
+};
+
+use num::Float;
+
+use crate::WsworEntry;
+
+/// How a [`StreamingWswor`](crate::StreamingWswor) stores its sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// A binary heap of the items along with their keys.
+    #[default]
+    Heap,
+    /// A binary heap of keys pointing into a separate slab of items, so that
+    /// sifting the heap only moves keys and indices around, never the items.
+    /// Pays off for large samples of large items.
+    ///
+    /// Holds at most `u32::MAX` items.
+    KeySlab,
+    /// A buffer of up to twice the sample size of candidates, cut down to the
+    /// ones with the smallest keys with a linear-time selection whenever it
+    /// fills up. Pays off for large samples when most items get in, where a
+    /// heap would be reordered for every one of them.
+    ///
+    /// Candidates are only rejected right away if they lose to the threshold
+    /// of the last cut, so [`feed`](crate::StreamingWswor::feed) returning
+    /// `true` means that an item was buffered, not that it will stay.
+    Buffer,
+}
+
+/// The storage behind [`StreamingWswor`](crate::StreamingWswor), holding the
+/// entries with the smallest keys.
+pub(crate) enum Reservoir<F: Float, T> {
+    Heap(BinaryHeap<WsworEntry<F, T>>),
+    KeySlab(KeySlab<F, T>),
+    Buffer(Buffer<F, T>),
+}
+
+impl<F: Float, T> Reservoir<F, T> {
+    pub(crate) fn new(
+        backend: Backend,
+        count: usize,
+    ) -> Reservoir<F, T> {
+        // one extra for the entry pushed right before the largest is dropped
+        Reservoir::with_capacity(backend, count, count.saturating_add(1))
+    }
+
+    /// A reservoir for `count` entries that only reserves room for
+    /// `capacity` up front.
+    pub(crate) fn with_capacity(
+        backend: Backend,
+        count: usize,
+        capacity: usize,
+    ) -> Reservoir<F, T> {
+        match backend {
+            Backend::Heap => {
+                Reservoir::Heap(BinaryHeap::with_capacity(capacity))
+            },
+            Backend::KeySlab => Reservoir::KeySlab(KeySlab {
+                keys: BinaryHeap::with_capacity(capacity),
+                items: Vec::with_capacity(capacity),
+                free: Vec::new(),
+            }),
+            Backend::Buffer => Reservoir::Buffer(Buffer {
+                count,
+                entries: Vec::new(),
+                cutoff: None,
+            }),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Reservoir::Heap(heap) => heap.len(),
+            Reservoir::KeySlab(slab) => slab.keys.len(),
+            Reservoir::Buffer(buffer) => buffer.entries.len().min(buffer.count),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The largest key in the sample.
+    pub(crate) fn peek(&self) -> Option<F> {
+        match self {
+            Reservoir::Heap(heap) => heap.peek().map(|entry| entry.weight),
+            Reservoir::KeySlab(slab) => slab.keys.peek().map(|key| key.weight),
+            Reservoir::Buffer(buffer) => buffer.largest().map(|key| key.weight),
+        }
+    }
+
+    /// A key no smaller than the largest in the sample, which is all it takes
+    /// to rule out items. Unlike [`peek`](Reservoir::peek), it doesn't look
+    /// for the largest key among the candidates of a buffer that has been cut
+    /// before, but gives the cutoff of the last cut, which only gets looser.
+    pub(crate) fn bound(&self) -> Option<F> {
+        match self {
+            Reservoir::Buffer(Buffer {
+                cutoff: Some(cutoff),
+                ..
+            }) => Some(cutoff.weight),
+            _ => self.peek(),
+        }
+    }
+
+    /// Inserts an entry if it is among the `count` smallest, returning whether
+    /// it was.
+    pub(crate) fn insert(
+        &mut self,
+        entry: WsworEntry<F, T>,
+        count: usize,
+    ) -> bool {
+        // a really fast guard so we don't have to push and then drop the same
+        // element in case what we're inserting won't really make it into the
+        // list
+        let beaten = match self {
+            Reservoir::Heap(heap) => {
+                heap.peek().is_some_and(|top| top.rank(&entry).is_lt())
+            },
+            Reservoir::KeySlab(slab) => {
+                slab.keys.peek().is_some_and(|top| top.rank(&entry).is_lt())
+            },
+            Reservoir::Buffer(buffer) => buffer
+                .cutoff
+                .as_ref()
+                .is_some_and(|top| top.rank(&entry).is_lt()),
+        };
+
+        if count <= self.len() && beaten {
+            return false;
+        }
+
+        match self {
+            Reservoir::Heap(heap) => heap.push(entry),
+            Reservoir::KeySlab(slab) => slab.push(entry),
+            Reservoir::Buffer(buffer) => {
+                buffer.entries.push(entry);
+
+                // the first cut comes as soon as the sample is overfull, so
+                // that there always is a cutoff to rule out items with
+                let len = buffer.entries.len();
+                if count.saturating_mul(2) <= len
+                    || (buffer.cutoff.is_none() && count < len)
+                {
+                    buffer.compact();
+                }
+
+                return true;
+            },
+        }
+
+        self.shrink_to(count);
+
+        true
+    }
+
+    /// Drops the entries with the largest keys until at most `count` are left.
+    pub(crate) fn shrink_to(
+        &mut self,
+        count: usize,
+    ) {
+        if let Reservoir::Buffer(buffer) = self {
+            buffer.count = buffer.count.min(count);
+            buffer.compact();
+            return;
+        }
+
+        while count < self.len() {
+            match self {
+                Reservoir::Heap(heap) => {
+                    heap.pop();
+                },
+                Reservoir::KeySlab(slab) => {
+                    slab.pop();
+                },
+                Reservoir::Buffer(_) => unreachable!(),
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        let (heap, slab, buffer) = match self {
+            Reservoir::Heap(heap) => (Some(heap), None, None),
+            Reservoir::KeySlab(slab) => (None, Some(slab), None),
+            Reservoir::Buffer(buffer) => (None, None, Some(buffer)),
+        };
+
+        let heap = heap.into_iter().flat_map(|heap| heap.iter());
+        let slab = slab.into_iter().flat_map(|slab| slab.items.iter());
+        let buffer = buffer.into_iter().flat_map(|buffer| {
+            let largest = buffer.largest();
+
+            buffer.entries.iter().filter(move |entry| {
+                largest.as_ref().is_some_and(|top| !top.rank(entry).is_lt())
+            })
+        });
+
+        heap.chain(buffer)
+            .map(|entry| &entry.val)
+            .chain(slab.flatten())
+    }
+
+    /// The entries held, in no particular order.
+    pub(crate) fn into_vec(self) -> Vec<WsworEntry<F, T>> {
+        match self {
+            Reservoir::Heap(heap) => heap.into_vec(),
+            Reservoir::Buffer(mut buffer) => {
+                buffer.compact();
+                buffer.entries
+            },
+            Reservoir::KeySlab(mut slab) => {
+                let keys = core::mem::take(&mut slab.keys);
+
+                keys.into_iter()
+                    .map(|key| WsworEntry {
+                        weight: key.weight,
+                        index: key.index,
+                        val: slab.items[key.val as usize].take().unwrap(),
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
+/// Keys in a heap of their own, each pointing at a slot of the item slab.
+pub(crate) struct KeySlab<F: Float, T> {
+    keys: BinaryHeap<WsworEntry<F, u32>>,
+    items: Vec<Option<T>>,
+    // slots of the slab that were freed and can be reused
+    free: Vec<u32>,
+}
+
+impl<F: Float, T> KeySlab<F, T> {
+    fn push(
+        &mut self,
+        entry: WsworEntry<F, T>,
+    ) {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.items[slot as usize] = Some(entry.val);
+                slot
+            },
+            None => {
+                let slot = u32::try_from(self.items.len()).expect(
+                    "the key-slab backend holds at most u32::MAX items",
+                );
+                self.items.push(Some(entry.val));
+                slot
+            },
+        };
+
+        self.keys.push(WsworEntry {
+            weight: entry.weight,
+            index: entry.index,
+            val: slot,
+        });
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let key = self.keys.pop()?;
+        self.free.push(key.val);
+        self.items[key.val as usize].take()
+    }
+}
+
+/// Candidates in no particular order, of which the sample is the `count` with
+/// the smallest keys.
+pub(crate) struct Buffer<F: Float, T> {
+    count: usize,
+    entries: Vec<WsworEntry<F, T>>,
+    // the largest key kept by the last cut; nothing above it can get in
+    cutoff: Option<WsworEntry<F, ()>>,
+}
+
+impl<F: Float, T> Buffer<F, T> {
+    /// Cuts the candidates down to the sample.
+    fn compact(&mut self) {
+        if self.entries.len() <= self.count {
+            return;
+        }
+
+        if self.count == 0 {
+            self.entries.clear();
+            self.cutoff = None;
+            return;
+        }
+
+        self.entries.select_nth_unstable(self.count - 1);
+        self.entries.truncate(self.count);
+        self.cutoff = Some(self.entries[self.count - 1].key());
+    }
+
+    /// The largest key in the sample, found without cutting the candidates
+    /// down.
+    fn largest(&self) -> Option<WsworEntry<F, ()>> {
+        if self.entries.len() <= self.count {
+            return self.entries.iter().max().map(WsworEntry::key);
+        }
+
+        if self.count == 0 {
+            return None;
+        }
+
+        let mut keys: Vec<_> =
+            self.entries.iter().map(WsworEntry::key).collect();
+        keys.select_nth_unstable(self.count - 1);
+        keys.truncate(self.count);
+        keys.pop()
+    }
+}
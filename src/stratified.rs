@@ -0,0 +1,426 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    hash::Hash,
+    vec,
+    vec::Vec,
+};
+
+use num::{
+    Float,
+    One,
+    Zero,
+};
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::{
+    HasInvalidWeights,
+    StreamingWswor,
+    Weight,
+};
+
+/// What [`StratifiedWswor`] does with the items of a new stratum once it holds
+/// as many strata as it is allowed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrataOverflow<K> {
+    /// The items are dropped.
+    Drop,
+    /// The items are fed into this stratum instead. It doesn't count towards
+    /// the cap on strata.
+    Redirect(K),
+}
+
+/// One-pass Weighted Random Sampler Without Replacement that keeps a separate
+/// sample for every key.
+pub struct StratifiedWswor<K, W: Weight, T> {
+    count: usize,
+    counts: HashMap<K, usize>,
+    strata: HashMap<K, StreamingWswor<W, T>>,
+    max_strata: Option<(usize, StrataOverflow<K>)>,
+}
+
+impl<K, W, T> StratifiedWswor<K, W, T>
+where
+    K: Eq + Hash + Clone,
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    /// Creates a sampler that samples `count` items from every stratum.
+    pub fn new(count: usize) -> StratifiedWswor<K, W, T> {
+        StratifiedWswor {
+            count,
+            counts: HashMap::new(),
+            strata: HashMap::new(),
+            max_strata: None,
+        }
+    }
+
+    /// Caps the number of strata, deciding what happens to the items of the
+    /// strata that don't fit with `overflow`.
+    pub fn with_max_strata(
+        mut self,
+        max_strata: usize,
+        overflow: StrataOverflow<K>,
+    ) -> StratifiedWswor<K, W, T> {
+        self.max_strata = Some((max_strata, overflow));
+        self
+    }
+
+    /// Overrides the sample size of a single stratum.
+    ///
+    /// NOTE: this only applies to strata that haven't been fed yet
+    pub fn set_count(
+        &mut self,
+        key: K,
+        count: usize,
+    ) {
+        self.counts.insert(key, count);
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (K, W, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (k, w, v) in iter {
+            self.feed(k, v, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn feed<R: RngCore>(
+        &mut self,
+        key: K,
+        val: T,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        let key = match self.stratum_key(key) {
+            Some(key) => key,
+            None => return Ok(false),
+        };
+
+        let count = *self.counts.get(&key).unwrap_or(&self.count);
+
+        self.strata
+            .entry(key)
+            .or_insert_with(|| StreamingWswor::new(count))
+            .feed(val, weight, rng)
+    }
+
+    /// The stratum the items of `key` go to, if any.
+    fn stratum_key(
+        &self,
+        key: K,
+    ) -> Option<K> {
+        let Some((max_strata, overflow)) = &self.max_strata
+        else {
+            return Some(key);
+        };
+
+        if self.strata.contains_key(&key) {
+            return Some(key);
+        }
+
+        let overflow_key = match overflow {
+            StrataOverflow::Drop => None,
+            StrataOverflow::Redirect(overflow_key) => Some(overflow_key),
+        };
+
+        if overflow_key == Some(&key) {
+            return Some(key);
+        }
+
+        let len = self.strata.len()
+            - overflow_key.map_or(0, |k| self.strata.contains_key(k) as usize);
+
+        if len < *max_strata {
+            Some(key)
+        }
+        else {
+            overflow_key.cloned()
+        }
+    }
+
+    /// Number of strata that have been fed.
+    pub fn len(&self) -> usize {
+        self.strata.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strata.is_empty()
+    }
+
+    pub fn get(
+        &self,
+        key: &K,
+    ) -> Option<&StreamingWswor<W, T>> {
+        self.strata.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &StreamingWswor<W, T>)> {
+        self.strata.iter()
+    }
+
+    pub fn take(self) -> HashMap<K, Vec<T>> {
+        self.strata
+            .into_iter()
+            .map(|(key, stratum)| (key, stratum.take().collect()))
+            .collect()
+    }
+}
+
+/// How [`AllocatedWswor`] splits its sample budget across strata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// Proportionally to the total weight of each stratum.
+    Proportional,
+    /// Proportionally to the total weight of each stratum times the square
+    /// root of its variance proxy, as set with
+    /// [`AllocatedWswor::set_variance_proxy`]. Strata without a proxy use one.
+    Neyman,
+}
+
+struct Stratum<W: Weight, T> {
+    sampler: StreamingWswor<W, T>,
+    total: W::Key,
+    spread: W::Key,
+    // how many strata were fed before this one, to break ties between them
+    // in a way that doesn't depend on the order of the hash map
+    order: usize,
+}
+
+/// One-pass Weighted Random Sampler Without Replacement that keeps a separate
+/// sample for every key, sharing a fixed number of items between them.
+///
+/// The budget is split according to the weight observed in every stratum so
+/// far. Since an item that was dropped can't be brought back, strata can only
+/// ever give up slots: whenever more than `budget` items are held, the strata
+/// holding more than their share are shrunk down to it. Strata that were
+/// shrunk early on keep their smaller size even if they receive more weight
+/// later, so the final sample sizes follow the allocation only as closely as
+/// the order of the stream allows.
+pub struct AllocatedWswor<K, W: Weight, T> {
+    budget: usize,
+    allocation: Allocation,
+    spreads: HashMap<K, W::Key>,
+    strata: HashMap<K, Stratum<W, T>>,
+    held: usize,
+}
+
+impl<K, W, T> AllocatedWswor<K, W, T>
+where
+    K: Eq + Hash + Clone,
+    W: Weight,
+    Exp1: Distribution<W::Key>,
+{
+    pub fn new(
+        budget: usize,
+        allocation: Allocation,
+    ) -> AllocatedWswor<K, W, T> {
+        AllocatedWswor {
+            budget,
+            allocation,
+            spreads: HashMap::new(),
+            strata: HashMap::new(),
+            held: 0,
+        }
+    }
+
+    /// Sets the variance proxy of a stratum for Neyman allocation.
+    pub fn set_variance_proxy(
+        &mut self,
+        key: K,
+        variance: W::Key,
+    ) -> Result<(), HasInvalidWeights> {
+        HasInvalidWeights::check_weight(&variance)?;
+
+        let spread = variance.sqrt();
+
+        if let Some(stratum) = self.strata.get_mut(&key) {
+            stratum.spread = spread;
+        }
+
+        self.spreads.insert(key, spread);
+        Ok(())
+    }
+
+    /// NOTE: the consumption of the iterator will be halted prematurely if an
+    /// invalid weight is detected
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = (K, W, T)>,
+        rng: &mut R,
+    ) -> Result<(), HasInvalidWeights> {
+        for (k, w, v) in iter {
+            self.feed(k, v, w, rng)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn feed<R: RngCore>(
+        &mut self,
+        key: K,
+        val: T,
+        weight: W,
+        rng: &mut R,
+    ) -> Result<bool, HasInvalidWeights> {
+        weight.validate()?;
+
+        let budget = self.budget;
+        let spread = *self.spreads.get(&key).unwrap_or(&W::Key::one());
+        let order = self.strata.len();
+        // the strata share the budget, so each only grows as it needs to
+        let stratum = self.strata.entry(key).or_insert_with(|| Stratum {
+            sampler: StreamingWswor::with_capacity(budget, 0),
+            total: W::Key::zero(),
+            spread,
+            order,
+        });
+
+        let before = stratum.sampler.len();
+        stratum.total = stratum.total + weight.to_key();
+        let accepted = stratum.sampler.feed(val, weight, rng)?;
+        self.held = self.held + stratum.sampler.len() - before;
+
+        if self.budget < self.held {
+            self.rebalance();
+        }
+
+        Ok(accepted)
+    }
+
+    /// Shrinks every stratum that holds more than its share of the budget.
+    /// Slots that a stratum can't fill because it holds too few items go to the
+    /// others.
+    fn rebalance(&mut self) {
+        let mut strata: Vec<_> = self.strata.values_mut().collect();
+        strata.sort_by_key(|stratum| stratum.order);
+
+        let shares: Vec<_> = strata
+            .iter()
+            .map(|stratum| {
+                let share = match self.allocation {
+                    Allocation::Proportional => stratum.total,
+                    Allocation::Neyman => stratum.total * stratum.spread,
+                };
+
+                // a share that overflowed still outweighs the finite ones
+                (share.min(W::Key::max_value()), stratum.sampler.len())
+            })
+            .collect();
+
+        let targets = allocate(&shares, self.budget);
+
+        for (stratum, target) in strata.into_iter().zip(targets) {
+            if target < stratum.sampler.len() {
+                self.held -= stratum.sampler.len() - target;
+                stratum.sampler.shrink_to(target);
+            }
+        }
+    }
+
+    /// Number of strata that have been fed.
+    pub fn len(&self) -> usize {
+        self.strata.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strata.is_empty()
+    }
+
+    pub fn get(
+        &self,
+        key: &K,
+    ) -> Option<&StreamingWswor<W, T>> {
+        self.strata.get(key).map(|stratum| &stratum.sampler)
+    }
+
+    /// Total weight fed into a stratum.
+    pub fn stratum_weight(
+        &self,
+        key: &K,
+    ) -> Option<W::Key> {
+        self.strata.get(key).map(|stratum| stratum.total)
+    }
+
+    pub fn take(self) -> HashMap<K, Vec<T>> {
+        self.strata
+            .into_iter()
+            .map(|(key, stratum)| (key, stratum.sampler.take().collect()))
+            .collect()
+    }
+}
+
+/// Splits `budget` proportionally to the shares, without giving any of them
+/// more than its cap. Whole slots are handed out by largest remainder, ties
+/// going to the earlier shares.
+fn allocate<F: Float>(
+    shares: &[(F, usize)],
+    budget: usize,
+) -> Vec<usize> {
+    let mut targets = vec![0; shares.len()];
+    let mut open: Vec<_> = (0 .. shares.len())
+        .filter(|&i| shares[i].0 > F::zero() && shares[i].1 > 0)
+        .collect();
+    let mut budget = budget;
+    let to_float = |n: usize| F::from(n).unwrap_or_else(F::max_value);
+
+    loop {
+        // the shares are taken relative to the largest one so that their
+        // total can't overflow
+        let largest = open
+            .iter()
+            .fold(F::zero(), |largest, &i| largest.max(shares[i].0));
+        let share = |i: usize| shares[i].0 / largest;
+        let total = open.iter().fold(F::zero(), |sum, &i| sum + share(i));
+        let available = to_float(budget);
+        let ideal = |i: usize| available * share(i) / total;
+
+        // strata that would go over their cap are settled at the cap, which
+        // leaves more for the rest
+        let (capped, uncapped): (Vec<_>, Vec<_>) = open
+            .iter()
+            .partition(|&&i| to_float(shares[i].1) <= ideal(i));
+
+        if capped.is_empty() {
+            let mut remainders: Vec<_> = uncapped
+                .iter()
+                .map(|&i| {
+                    let ideal = ideal(i);
+                    targets[i] = ideal.floor().to_usize().unwrap_or(0);
+                    budget -= targets[i];
+                    (ideal - ideal.floor(), i)
+                })
+                .collect();
+
+            // the sort is stable, so equal remainders stay in order
+            remainders.sort_by(|a, b| {
+                b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal)
+            });
+
+            for (_, i) in remainders.into_iter().take(budget) {
+                targets[i] += 1;
+            }
+
+            return targets;
+        }
+
+        for i in capped {
+            targets[i] = shares[i].1;
+            budget -= shares[i].1;
+        }
+
+        open = uncapped;
+    }
+}
@@ -1,5 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    dbg,
+    format,
+    hash::Hasher,
+    prelude::rust_2021::*,
+    vec,
+};
 
+use num::rational::Ratio;
 use rand::{
     rngs::StdRng,
     SeedableRng,
@@ -286,8 +294,8 @@ fn test_single_streaming_proportionality() {
     let trials = 10000;
 
     // Items with weights 1:2:3:4 (total weight = 10)
-    let items = vec![(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')];
-    let expected_probs = vec![0.1, 0.2, 0.3, 0.4];
+    let items = [(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')];
+    let expected_probs = [0.1, 0.2, 0.3, 0.4];
 
     for _ in 0 .. trials {
         let mut sampler: SingleStreamingWs<f64, char> =
@@ -366,7 +374,7 @@ fn test_single_streaming_uniform_distribution() {
     let expected = trials / 8;
     for i in 1 ..= 8 {
         let count = *counts.get(&i).unwrap_or(&0);
-        let deviation = (count as i32 - expected as i32).abs();
+        let deviation = i32::abs(count - expected);
         assert!(
             deviation < 200,
             "Item {} count {} deviates too much from expected {}",
@@ -424,8 +432,7 @@ fn test_streaming_wswor_proportionality() {
     let trials = 5000;
 
     // Items with weights 1:2:3:4:5 (total weight = 15)
-    let items =
-        vec![(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D'), (5.0, 'E')];
+    let items = [(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D'), (5.0, 'E')];
 
     for _ in 0 .. trials {
         let mut sampler: StreamingWswor<f64, char> = StreamingWswor::new(3);
@@ -464,7 +471,7 @@ fn test_streaming_wswor_proportionality() {
 #[test]
 fn test_streaming_wswor_sample_size_independence() {
     let mut rng = StdRng::seed_from_u64(11111);
-    let items = vec![(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')];
+    let items = [(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')];
     let trials = 2000;
 
     // Test different sample sizes
@@ -532,7 +539,7 @@ fn test_streaming_wswor_uniform_distribution() {
     let expected = (trials * sample_size) / (num_items as usize);
     for i in 1 ..= num_items {
         let count = *counts.get(&i).unwrap_or(&0);
-        let deviation = (count as i32 - expected as i32).abs();
+        let deviation = (count - expected as i32).abs();
         assert!(
             deviation < 300,
             "Item {} count {} deviates too much from expected {}",
@@ -659,3 +666,2157 @@ fn test_streaming_wswor_zero_weight_mixed() {
     // D should appear in most samples (high weight + sample size 2)
     assert!(count_d as f64 / trials as f64 > 0.8);
 }
+
+/// Tests basic UniformReservoir functionality - feeds 100 items to a
+/// reservoir with capacity 5, verifies exactly 5 distinct items come back.
+#[test]
+fn test_uniform_reservoir_basic() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: UniformReservoir<i32> = UniformReservoir::new(5);
+
+    sampler.feed_iter(0 .. 100, &mut rng);
+    assert!(sampler.is_full());
+    assert_eq!(sampler.iter().count(), 5);
+
+    let mut results: Vec<_> = sampler.take().collect();
+    results.sort();
+    results.dedup();
+    assert_eq!(results.len(), 5);
+}
+
+/// Tests that the geometric skips of Algorithm L don't bias the sample -
+/// with 3 out of 30 items sampled, each item should appear in roughly 10%
+/// of the samples, including the late ones that are mostly skipped over.
+#[test]
+fn test_uniform_reservoir_uniform_distribution() {
+    let mut rng = StdRng::seed_from_u64(33333);
+    let mut counts = HashMap::new();
+    let trials = 10000;
+
+    for _ in 0 .. trials {
+        let mut sampler: UniformReservoir<i32> = UniformReservoir::new(3);
+        sampler.feed_iter(0 .. 30, &mut rng);
+
+        for result in sampler.take() {
+            *counts.entry(result).or_insert(0) += 1;
+        }
+    }
+
+    let expected = trials * 3 / 30;
+    for i in 0 .. 30 {
+        let count: i32 = *counts.get(&i).unwrap_or(&0);
+        let deviation = (count - expected).abs();
+        assert!(
+            deviation < 150,
+            "Item {} count {} deviates too much from expected {}",
+            i,
+            count,
+            expected
+        );
+    }
+}
+
+/// Tests the constant-weight fast path of StreamingWswor - a stream of equal
+/// weights should be sampled uniformly, the same as without the fast path.
+#[test]
+fn test_streaming_wswor_constant_weight_uniform() {
+    let mut rng = StdRng::seed_from_u64(55555);
+    let mut counts = HashMap::new();
+    let trials = 10000;
+
+    for _ in 0 .. trials {
+        let mut sampler: StreamingWswor<f64, i32> =
+            StreamingWswor::new(3).detect_constant_weight();
+        for i in 0 .. 30 {
+            sampler.feed(i, 2.5, &mut rng).unwrap();
+        }
+
+        assert_eq!(sampler.iter().count(), 3);
+        for result in sampler.take() {
+            *counts.entry(result).or_insert(0) += 1;
+        }
+    }
+
+    let expected = trials * 3 / 30;
+    for i in 0 .. 30 {
+        let count: i32 = *counts.get(&i).unwrap_or(&0);
+        let deviation = (count - expected).abs();
+        assert!(
+            deviation < 150,
+            "Item {} count {} deviates too much from expected {}",
+            i,
+            count,
+            expected
+        );
+    }
+}
+
+/// Tests that the constant-weight fast path hands over to the weighted
+/// sampler once a differing weight shows up - a single heavy item fed after
+/// a long run of light ones should almost always be selected.
+#[test]
+fn test_streaming_wswor_constant_weight_switch() {
+    let mut rng = StdRng::seed_from_u64(66666);
+    let trials = 2000;
+    let mut heavy_selected = 0;
+
+    for _ in 0 .. trials {
+        let mut sampler: StreamingWswor<f64, i32> =
+            StreamingWswor::new(2).detect_constant_weight();
+        for i in 0 .. 50 {
+            sampler.feed(i, 1.0, &mut rng).unwrap();
+        }
+        sampler.feed(-1, 1000.0, &mut rng).unwrap();
+
+        let results: Vec<_> = sampler.take().collect();
+        assert_eq!(results.len(), 2);
+        if results.contains(&-1) {
+            heavy_selected += 1;
+        }
+    }
+
+    assert!(heavy_selected as f64 / trials as f64 > 0.9);
+}
+
+const INDEX_ALGORITHMS: [IndexAlgorithm; 5] = [
+    IndexAlgorithm::Auto,
+    IndexAlgorithm::Rejection,
+    IndexAlgorithm::Rank,
+    IndexAlgorithm::ExpJumps,
+    IndexAlgorithm::Bucket,
+];
+
+/// Tests that every index sampling algorithm returns the requested number
+/// of distinct, in-range indices, and never more than there are weights.
+#[test]
+fn test_sample_indices_basic() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let weights: Vec<f64> = (1 ..= 50).map(|i| i as f64).collect();
+
+    for algorithm in INDEX_ALGORITHMS {
+        for count in [0, 1, 5, 25, 50, 80] {
+            let mut results =
+                sample_indices(&weights, count, algorithm, &mut rng).unwrap();
+            assert_eq!(results.len(), count.min(50), "{:?}", algorithm);
+
+            results.sort();
+            results.dedup();
+            assert_eq!(results.len(), count.min(50), "{:?}", algorithm);
+            assert!(results.iter().all(|&i| i < 50));
+        }
+    }
+}
+
+/// Tests that the indices come back in draw order - the first index of each
+/// sample should follow the 1:2:3:4 weights exactly like a single draw.
+/// Uses a chi-square test for every algorithm.
+#[test]
+fn test_sample_indices_draw_order() {
+    let mut rng = StdRng::seed_from_u64(12345);
+    let weights = [1.0, 2.0, 3.0, 4.0];
+    let expected_probs = [0.1, 0.2, 0.3, 0.4];
+    let trials = 10000;
+
+    for algorithm in INDEX_ALGORITHMS {
+        let mut counts = [0; 4];
+
+        for _ in 0 .. trials {
+            let results =
+                sample_indices(&weights, 3, algorithm, &mut rng).unwrap();
+            counts[results[0]] += 1;
+        }
+
+        let mut chi_square = 0.0;
+        for i in 0 .. 4 {
+            let expected = trials as f64 * expected_probs[i];
+            chi_square += (counts[i] as f64 - expected).powi(2) / expected;
+        }
+
+        // Critical value for 3 degrees of freedom at 0.01 significance level
+        assert!(
+            chi_square < 11.345,
+            "{:?}: chi-square value {} exceeds critical value",
+            algorithm,
+            chi_square
+        );
+    }
+}
+
+/// Tests that all algorithms agree on the inclusion frequencies of a sample
+/// of 2 out of 4 items, which depend on the successive-draw probabilities
+/// rather than the weights alone.
+#[test]
+fn test_sample_indices_inclusion_frequencies() {
+    let mut rng = StdRng::seed_from_u64(24680);
+    let weights = [1.0, 2.0, 3.0, 4.0];
+    let trials = 10000;
+
+    // P(i in sample) = p_i + sum over j != i of p_j * p_i / (1 - p_j)
+    let p = [0.1, 0.2, 0.3, 0.4];
+    let expected: Vec<f64> = (0 .. 4)
+        .map(|i| {
+            p[i] + (0 .. 4)
+                .filter(|&j| j != i)
+                .map(|j| p[j] * p[i] / (1.0 - p[j]))
+                .sum::<f64>()
+        })
+        .collect();
+
+    for algorithm in INDEX_ALGORITHMS {
+        let mut counts = [0; 4];
+
+        for _ in 0 .. trials {
+            for i in sample_indices(&weights, 2, algorithm, &mut rng).unwrap() {
+                counts[i] += 1;
+            }
+        }
+
+        for i in 0 .. 4 {
+            let frequency = counts[i] as f64 / trials as f64;
+            assert!(
+                (frequency - expected[i]).abs() < 0.03,
+                "{:?}: item {} frequency {} vs expected {}",
+                algorithm,
+                i,
+                frequency,
+                expected[i]
+            );
+        }
+    }
+}
+
+/// Tests that zero-weight indices are only used once the positive weights
+/// run out, and that invalid weights are rejected by every algorithm.
+#[test]
+fn test_sample_indices_zero_and_invalid_weights() {
+    let mut rng = StdRng::seed_from_u64(13579);
+    let weights = [0.0, 1.0, 0.0, 2.0, 0.0];
+
+    for algorithm in INDEX_ALGORITHMS {
+        let results = sample_indices(&weights, 2, algorithm, &mut rng).unwrap();
+        let mut sorted = results.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![1, 3]);
+
+        let results = sample_indices(&weights, 4, algorithm, &mut rng).unwrap();
+        assert!(results[.. 2].contains(&1));
+        assert!(results[.. 2].contains(&3));
+        assert!(results[2 ..].iter().all(|&i| weights[i] == 0.0));
+
+        assert!(sample_indices(&[1.0, -1.0], 1, algorithm, &mut rng).is_err());
+        assert!(
+            sample_indices(&[1.0, f64::NAN], 1, algorithm, &mut rng).is_err()
+        );
+    }
+}
+
+/// Tests that sampling finishes when a few items hold nearly all the weight -
+/// the light items' cumulative weights round to the same values, so they can
+/// only be drawn once the heavy ones are out of the way.
+#[test]
+fn test_sample_indices_skewed_weights() {
+    let mut rng = StdRng::seed_from_u64(97531);
+    let mut weights = vec![1e-300; 95];
+    weights.extend([1.0; 5]);
+
+    for algorithm in INDEX_ALGORITHMS {
+        let results =
+            sample_indices(&weights, 10, algorithm, &mut rng).unwrap();
+        let mut heavy = results[.. 5].to_vec();
+        heavy.sort();
+        assert_eq!(heavy, [95, 96, 97, 98, 99], "{:?}", algorithm);
+
+        let mut sorted = results.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 10, "{:?}", algorithm);
+    }
+}
+
+/// Tests weights at both ends of the float range - near the largest float,
+/// where the bucket bounds would overflow, and subnormal, where they would
+/// underflow. Heavier items should still be drawn first.
+#[test]
+fn test_sample_indices_extreme_weights() {
+    let mut rng = StdRng::seed_from_u64(86420);
+    let subnormal = f64::from_bits(1);
+
+    for algorithm in INDEX_ALGORITHMS {
+        let results =
+            sample_indices(&[1e308, 1.0], 1, algorithm, &mut rng).unwrap();
+        assert_eq!(results, [0], "{:?}", algorithm);
+    }
+
+    let weights = [subnormal, f64::MAX, 1.0, f64::MIN_POSITIVE];
+    let results =
+        sample_indices(&weights, 4, IndexAlgorithm::Bucket, &mut rng).unwrap();
+    assert_eq!(results, [1, 2, 3, 0]);
+}
+
+/// Tests weights whose total overflows - sampling should still finish, and
+/// equal weights should still be drawn equally often.
+#[test]
+fn test_sample_indices_overflowing_weights() {
+    let mut rng = StdRng::seed_from_u64(24680);
+
+    for algorithm in INDEX_ALGORITHMS {
+        let results =
+            sample_indices(&[1e308; 20], 2, algorithm, &mut rng).unwrap();
+        assert_eq!(results.len(), 2, "{:?}", algorithm);
+        assert_ne!(results[0], results[1], "{:?}", algorithm);
+
+        let mut counts = [0; 3];
+
+        for _ in 0 .. 3000 {
+            let results =
+                sample_indices(&[1e308; 3], 1, algorithm, &mut rng).unwrap();
+            counts[results[0]] += 1;
+        }
+
+        for count in counts {
+            assert!((800 .. 1200).contains(&count), "{:?}", algorithm);
+        }
+    }
+}
+
+/// Tests the bookkeeping of DynamicWeightedSet - inserting, updating and
+/// removing keys should keep the length, weights and total weight in sync,
+/// and freed slots should be reused.
+#[test]
+fn test_dynamic_weighted_set_basic() {
+    let mut set: DynamicWeightedSet<&str, f64> = DynamicWeightedSet::new();
+
+    assert!(set.is_empty());
+    assert_eq!(set.insert("a", 1.0).unwrap(), None);
+    assert_eq!(set.insert("b", 2.0).unwrap(), None);
+    assert_eq!(set.insert("c", 3.0).unwrap(), None);
+    assert_eq!(set.len(), 3);
+    assert_eq!(set.total_weight(), 6.0);
+
+    assert_eq!(set.insert("a", 4.0).unwrap(), Some(1.0));
+    assert_eq!(set.set_weight(&"b", 0.5).unwrap(), Some(2.0));
+    assert_eq!(set.set_weight(&"z", 0.5).unwrap(), None);
+    assert!(!set.contains_key(&"z"));
+    assert_eq!(set.total_weight(), 7.5);
+
+    assert_eq!(set.remove(&"c"), Some(3.0));
+    assert_eq!(set.remove(&"c"), None);
+    assert_eq!(set.total_weight(), 4.5);
+
+    set.insert("d", 1.5).unwrap();
+    assert_eq!(set.len(), 3);
+    assert_eq!(set.weight(&"d"), Some(1.5));
+    assert_eq!(set.total_weight(), 6.0);
+
+    assert!(set.insert("e", -1.0).is_err());
+    assert!(set.set_weight(&"a", f64::NAN).is_err());
+    assert_eq!(set.weight(&"a"), Some(4.0));
+}
+
+/// Tests that sampling returns distinct keys, leaves the weights untouched,
+/// and only uses zero-weight keys once the positive ones run out.
+#[test]
+fn test_dynamic_weighted_set_sample_distinct() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut set: DynamicWeightedSet<i32, f64> = DynamicWeightedSet::new();
+
+    for i in 0 .. 100 {
+        set.insert(i, if i % 10 == 0 { 0.0 } else { i as f64 })
+            .unwrap();
+    }
+    let total = set.total_weight();
+
+    let mut results: Vec<_> = set
+        .sample_without_replacement(90, &mut rng)
+        .into_iter()
+        .copied()
+        .collect();
+    assert!(results.iter().all(|i| i % 10 != 0));
+    results.sort();
+    results.dedup();
+    assert_eq!(results.len(), 90);
+
+    let results = set.sample_without_replacement(95, &mut rng);
+    assert_eq!(results.len(), 95);
+    assert!(results[90 ..].iter().all(|&&i| i % 10 == 0));
+
+    assert_eq!(set.sample_without_replacement(200, &mut rng).len(), 100);
+    assert!((set.total_weight() - total).abs() < 1e-9);
+}
+
+/// Tests that weights which would make the total infinite are rejected, so
+/// that sampling near the largest float still finishes.
+#[test]
+fn test_dynamic_weighted_set_overflowing_weights() {
+    let mut rng = StdRng::seed_from_u64(13579);
+    let mut set: DynamicWeightedSet<i32, f64> = DynamicWeightedSet::new();
+
+    set.insert(0, 1e308).unwrap();
+    assert!(matches!(
+        set.insert(1, 1e308),
+        Err(HasInvalidWeights::Infinite)
+    ));
+    assert!(!set.contains_key(&1));
+
+    set.insert(1, 1e307).unwrap();
+    assert!(matches!(
+        set.set_weight(&1, 1e308),
+        Err(HasInvalidWeights::Infinite)
+    ));
+    assert!(matches!(
+        set.insert(0, f64::MAX),
+        Err(HasInvalidWeights::Infinite)
+    ));
+    assert_eq!(set.weight(&1), Some(1e307));
+
+    // lowering a weight frees room for the others
+    set.set_weight(&0, 5e307).unwrap();
+    set.insert(1, 1e308).unwrap();
+
+    let mut results = set.sample_without_replacement(2, &mut rng);
+    results.sort();
+    assert_eq!(results, [&0, &1]);
+}
+
+/// Tests that samples follow the current weights after updates - the first
+/// draw should match the 1:2:3:4 weights set after the keys were inserted
+/// with different ones. Uses a chi-square test.
+#[test]
+fn test_dynamic_weighted_set_proportionality() {
+    let mut rng = StdRng::seed_from_u64(12345);
+    let mut set: DynamicWeightedSet<char, f64> = DynamicWeightedSet::new();
+    let trials = 10000;
+
+    for (weight, key) in [(9.0, 'A'), (1.0, 'B'), (5.0, 'X'), (1.0, 'C')] {
+        set.insert(key, weight).unwrap();
+    }
+    set.set_weight(&'A', 1.0).unwrap();
+    set.set_weight(&'B', 2.0).unwrap();
+    set.set_weight(&'C', 3.0).unwrap();
+    set.remove(&'X');
+    set.insert('D', 4.0).unwrap();
+
+    let mut counts = HashMap::new();
+    for _ in 0 .. trials {
+        let result = *set.sample_without_replacement(2, &mut rng)[0];
+        *counts.entry(result).or_insert(0) += 1;
+    }
+
+    let mut chi_square = 0.0;
+    for (i, &item) in ['A', 'B', 'C', 'D'].iter().enumerate() {
+        let observed = *counts.get(&item).unwrap_or(&0) as f64;
+        let expected = trials as f64 * (i + 1) as f64 / 10.0;
+        chi_square += (observed - expected).powi(2) / expected;
+    }
+
+    // Critical value for 3 degrees of freedom at 0.01 significance level
+    assert!(
+        chi_square < 11.345,
+        "Chi-square value {} exceeds critical value",
+        chi_square
+    );
+}
+
+/// Tests that AliasTable draws follow the 1:2:3:4 weights, including a
+/// zero-weight value that should never be drawn. Uses a chi-square test.
+#[test]
+fn test_alias_table_proportionality() {
+    let mut rng = StdRng::seed_from_u64(12345);
+    let items = [(1.0, 'A'), (0.0, 'X'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')];
+    let table = AliasTable::new(items.into_iter()).unwrap();
+    let trials = 10000;
+
+    assert_eq!(table.len(), 5);
+
+    let mut counts = HashMap::new();
+    for &result in table.sample_n(trials, &mut rng) {
+        *counts.entry(result).or_insert(0) += 1;
+    }
+
+    assert_eq!(counts.get(&'X'), None);
+
+    let mut chi_square = 0.0;
+    for (i, &item) in ['A', 'B', 'C', 'D'].iter().enumerate() {
+        let observed = *counts.get(&item).unwrap_or(&0) as f64;
+        let expected = trials as f64 * (i + 1) as f64 / 10.0;
+        chi_square += (observed - expected).powi(2) / expected;
+    }
+
+    // Critical value for 3 degrees of freedom at 0.01 significance level
+    assert!(
+        chi_square < 11.345,
+        "Chi-square value {} exceeds critical value",
+        chi_square
+    );
+}
+
+/// Tests the edge cases of AliasTable - empty and all-zero tables can't be
+/// sampled from, invalid weights are rejected, and sampling without
+/// replacement returns distinct values.
+#[test]
+fn test_alias_table_edge_cases() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let empty = AliasTable::<f64, i32>::new(core::iter::empty()).unwrap();
+    assert!(empty.is_empty());
+    assert!(empty.sample(&mut rng).is_none());
+    assert_eq!(empty.sample_n(10, &mut rng).count(), 0);
+
+    let zero = AliasTable::new([(0.0, 1), (0.0, 2)].into_iter()).unwrap();
+    assert!(zero.sample(&mut rng).is_none());
+    assert_eq!(zero.without_replacement(2, &mut rng).count(), 2);
+
+    assert!(AliasTable::new([(1.0, 1), (-1.0, 2)].into_iter()).is_err());
+    assert!(AliasTable::new([(1.0, 1), (f64::NAN, 2)].into_iter()).is_err());
+
+    let table = AliasTable::new((1 ..= 10).map(|i| (i as f64, i))).unwrap();
+    let mut results: Vec<_> = table.without_replacement(5, &mut rng).collect();
+    results.sort();
+    results.dedup();
+    assert_eq!(results.len(), 5);
+}
+
+/// Tests weights whose total overflows - the light item should still be
+/// drawn as rarely as its weight says, not a third of the time.
+#[test]
+fn test_alias_table_overflowing_weights() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let items = [(1e308, 0), (1e308, 1), (1.0, 2)];
+    let table = AliasTable::new(items.into_iter()).unwrap();
+
+    let mut counts = [0; 3];
+    for &result in table.sample_n(10000, &mut rng) {
+        counts[result] += 1;
+    }
+
+    assert_eq!(counts[2], 0);
+    assert!((4500 .. 5500).contains(&counts[0]));
+}
+
+/// Tests basic StratifiedWswor functionality - every stratum gets its own
+/// sample of the default size, except for the one with an overridden count.
+#[test]
+fn test_stratified_wswor_basic() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: StratifiedWswor<&str, f64, i32> = StratifiedWswor::new(3);
+    sampler.set_count("small", 1);
+
+    for i in 0 .. 10 {
+        sampler.feed("a", i, 1.0, &mut rng).unwrap();
+        sampler.feed("b", i + 100, 1.0, &mut rng).unwrap();
+        sampler.feed("small", i + 200, 1.0, &mut rng).unwrap();
+    }
+    sampler.feed("tiny", 300, 1.0, &mut rng).unwrap();
+    assert!(sampler.feed("a", 0, -1.0, &mut rng).is_err());
+
+    assert_eq!(sampler.len(), 4);
+    assert!(sampler.get(&"a").unwrap().is_full());
+
+    let results = sampler.take();
+    assert_eq!(results[&"a"].len(), 3);
+    assert!(results[&"a"].iter().all(|i| (0 .. 10).contains(i)));
+    assert_eq!(results[&"b"].len(), 3);
+    assert!(results[&"b"].iter().all(|i| (100 .. 110).contains(i)));
+    assert_eq!(results[&"small"].len(), 1);
+    assert_eq!(results[&"tiny"], vec![300]);
+}
+
+/// Tests the cap on strata - with Drop, items of new strata past the cap are
+/// dropped; with Redirect, they end up in the overflow stratum, which
+/// doesn't count towards the cap.
+#[test]
+fn test_stratified_wswor_max_strata() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let mut sampler: StratifiedWswor<i32, f64, i32> =
+        StratifiedWswor::new(2).with_max_strata(2, StrataOverflow::Drop);
+    assert!(sampler.feed(1, 1, 1.0, &mut rng).unwrap());
+    assert!(sampler.feed(2, 2, 1.0, &mut rng).unwrap());
+    assert!(!sampler.feed(3, 3, 1.0, &mut rng).unwrap());
+    assert!(sampler.feed(1, 4, 1.0, &mut rng).unwrap());
+
+    let results = sampler.take();
+    assert_eq!(results.len(), 2);
+    assert!(!results.contains_key(&3));
+
+    let mut sampler: StratifiedWswor<i32, f64, i32> = StratifiedWswor::new(10)
+        .with_max_strata(2, StrataOverflow::Redirect(-1));
+    sampler.feed(-1, 0, 1.0, &mut rng).unwrap();
+    for i in 1 ..= 5 {
+        sampler.feed(i, i, 1.0, &mut rng).unwrap();
+    }
+
+    let results = sampler.take();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[&1], vec![1]);
+    assert_eq!(results[&2], vec![2]);
+
+    let mut overflow = results[&-1].clone();
+    overflow.sort();
+    assert_eq!(overflow, vec![0, 3, 4, 5]);
+}
+
+/// Tests proportional allocation - two strata with a 1:3 total weight split
+/// a budget of 20 into 5 and 15 items, and the sampler never holds more
+/// than the budget (plus the item being fed) along the way.
+#[test]
+fn test_allocated_wswor_proportional() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: AllocatedWswor<char, f64, i32> =
+        AllocatedWswor::new(20, Allocation::Proportional);
+
+    for i in 0 .. 1000 {
+        sampler.feed('A', i, 1.0, &mut rng).unwrap();
+        sampler.feed('B', i, 3.0, &mut rng).unwrap();
+
+        let held: usize = ['A', 'B']
+            .iter()
+            .map(|key| sampler.get(key).unwrap().len())
+            .sum();
+        assert!(held <= 20);
+    }
+
+    assert_eq!(sampler.stratum_weight(&'B'), Some(3000.0));
+    assert!(sampler.feed('A', 0, f64::NAN, &mut rng).is_err());
+
+    let results = sampler.take();
+    assert_eq!(results[&'A'].len(), 5);
+    assert_eq!(results[&'B'].len(), 15);
+}
+
+/// Tests Neyman allocation - with equal total weights, strata with variance
+/// proxies of 9 and 1 should split the budget 3:1.
+#[test]
+fn test_allocated_wswor_neyman() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: AllocatedWswor<char, f64, i32> =
+        AllocatedWswor::new(20, Allocation::Neyman);
+    sampler.set_variance_proxy('A', 9.0).unwrap();
+    assert!(sampler.set_variance_proxy('B', -1.0).is_err());
+
+    for i in 0 .. 1000 {
+        sampler.feed('A', i, 1.0, &mut rng).unwrap();
+        sampler.feed('B', i, 1.0, &mut rng).unwrap();
+    }
+
+    let results = sampler.take();
+    assert_eq!(results[&'A'].len(), 15);
+    assert_eq!(results[&'B'].len(), 5);
+}
+
+/// Tests that slots a stratum can't fill go to the others - a stratum with
+/// most of the weight but only 2 items keeps both, and the rest of the
+/// budget goes to the strata that have items to spare.
+#[test]
+fn test_allocated_wswor_small_stratum() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: AllocatedWswor<char, f64, i32> =
+        AllocatedWswor::new(10, Allocation::Proportional);
+
+    sampler.feed('A', 0, 1000.0, &mut rng).unwrap();
+    sampler.feed('A', 1, 1000.0, &mut rng).unwrap();
+    for i in 0 .. 100 {
+        sampler.feed('B', i, 1.0, &mut rng).unwrap();
+    }
+
+    let results = sampler.take();
+    assert_eq!(results[&'A'].len(), 2);
+    assert_eq!(results[&'B'].len(), 8);
+}
+
+/// Tests that the strata of an AllocatedWswor don't each reserve room for the
+/// whole budget - a thousand strata sharing a budget of a million should only
+/// hold room for about what they hold.
+#[test]
+fn test_allocated_wswor_stratum_capacity() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: AllocatedWswor<u32, f64, u32> =
+        AllocatedWswor::new(1_000_000, Allocation::Proportional);
+
+    for key in 0 .. 1000 {
+        sampler.feed(key, key, 1.0, &mut rng).unwrap();
+    }
+
+    for key in 0 .. 1000 {
+        let Reservoir::Heap(heap) = &sampler.get(&key).unwrap().reservoir
+        else {
+            unreachable!()
+        };
+        assert!(heap.capacity() < 16);
+    }
+}
+
+/// Tests weights whose stratum totals overflow - the budget should still be
+/// split between the strata instead of panicking.
+#[test]
+fn test_allocated_wswor_overflowing_weights() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    for allocation in [Allocation::Proportional, Allocation::Neyman] {
+        let mut sampler: AllocatedWswor<u32, f64, u32> =
+            AllocatedWswor::new(2, allocation);
+
+        for i in 0 .. 10 {
+            sampler.feed(i % 2, i, 1e308, &mut rng).unwrap();
+        }
+
+        assert_eq!(sampler.get(&0).unwrap().len(), 1);
+        assert_eq!(sampler.get(&1).unwrap().len(), 1);
+    }
+}
+
+/// Tests that a fixed seed gives a fixed sample even when strata tie for the
+/// last slot of the budget, which hash map order used to decide.
+#[test]
+fn test_allocated_wswor_reproducible_ties() {
+    let sample = || {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut sampler: AllocatedWswor<u32, f64, u32> =
+            AllocatedWswor::new(3, Allocation::Proportional);
+
+        for i in 0 .. 20 {
+            sampler.feed(i % 2, i, 1.0, &mut rng).unwrap();
+        }
+
+        let mut sample: Vec<_> = sampler.take().into_iter().collect();
+        sample.sort();
+        sample
+    };
+
+    let first = sample();
+    assert_eq!(first[0].1.len(), 2);
+
+    for _ in 0 .. 50 {
+        assert_eq!(sample(), first);
+    }
+}
+
+/// Tests that CategoryCappedWswor never returns more than the cap per
+/// category, and still fills the sample from other categories when the
+/// heaviest category is capped.
+#[test]
+fn test_category_capped_wswor_cap() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    for _ in 0 .. 100 {
+        let mut sampler: CategoryCappedWswor<char, f64, i32> =
+            CategoryCappedWswor::new(5, 2);
+
+        for i in 0 .. 50 {
+            sampler.feed(i, 'A', 1000.0, &mut rng).unwrap();
+        }
+        for i in 50 .. 53 {
+            sampler.feed(i, 'B', 1.0, &mut rng).unwrap();
+        }
+        for i in 53 .. 100 {
+            sampler.feed(i, 'C', 0.001, &mut rng).unwrap();
+        }
+
+        assert!(sampler.is_full());
+        assert_eq!(sampler.iter().count(), 5);
+
+        let results: Vec<_> = sampler.take().collect();
+        let count_a = results.iter().filter(|&&i| i < 50).count();
+        let count_b =
+            results.iter().filter(|&&i| (50 .. 53).contains(&i)).count();
+        let count_c = results.iter().filter(|&&i| 53 <= i).count();
+        assert_eq!(count_a, 2);
+        assert_eq!(count_b, 2);
+        assert_eq!(count_c, 1);
+    }
+}
+
+/// Tests that fewer items come back when the categories can't fill the
+/// sample, and that invalid weights are rejected.
+#[test]
+fn test_category_capped_wswor_not_enough_categories() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: CategoryCappedWswor<char, f64, i32> =
+        CategoryCappedWswor::new(10, 3);
+
+    for i in 0 .. 20 {
+        sampler
+            .feed(i, if i % 2 == 0 { 'A' } else { 'B' }, 1.0, &mut rng)
+            .unwrap();
+    }
+    assert!(sampler.feed(0, 'A', -1.0, &mut rng).is_err());
+
+    assert!(!sampler.is_full());
+    assert_eq!(sampler.take().count(), 6);
+}
+
+/// Tests that the cap doesn't distort the weights within a category - with
+/// one slot per category, the item picked from a category with weights
+/// 1:2:3:4 should follow those weights. Uses a chi-square test.
+#[test]
+fn test_category_capped_wswor_proportionality() {
+    let mut rng = StdRng::seed_from_u64(12345);
+    let mut counts = HashMap::new();
+    let trials = 10000;
+
+    for _ in 0 .. trials {
+        let mut sampler: CategoryCappedWswor<i32, f64, char> =
+            CategoryCappedWswor::new(2, 1);
+        for (weight, value) in [(1.0, 'A'), (2.0, 'B'), (3.0, 'C'), (4.0, 'D')]
+        {
+            sampler.feed(value, 0, weight, &mut rng).unwrap();
+        }
+        sampler.feed('Z', 1, 1.0, &mut rng).unwrap();
+
+        for result in sampler.take() {
+            *counts.entry(result).or_insert(0) += 1;
+        }
+    }
+
+    assert_eq!(counts[&'Z'], trials);
+
+    let mut chi_square = 0.0;
+    for (i, &item) in ['A', 'B', 'C', 'D'].iter().enumerate() {
+        let observed = *counts.get(&item).unwrap_or(&0) as f64;
+        let expected = trials as f64 * (i + 1) as f64 / 10.0;
+        chi_square += (observed - expected).powi(2) / expected;
+    }
+
+    // Critical value for 3 degrees of freedom at 0.01 significance level
+    assert!(
+        chi_square < 11.345,
+        "Chi-square value {} exceeds critical value",
+        chi_square
+    );
+}
+
+/// Tests basic BudgetedWswor functionality - with a budget of 10 and items
+/// costing 3 each, exactly 3 items fit, and the 4th one in key order cuts
+/// the sample off.
+#[test]
+fn test_budgeted_wswor_basic() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: BudgetedWswor<f64, u64, i32> = BudgetedWswor::new(10);
+
+    for i in 0 .. 100 {
+        sampler.feed(i, 3, 1.0 + i as f64, &mut rng).unwrap();
+        assert!(sampler.spent() <= 10);
+    }
+
+    assert_eq!(sampler.len(), 3);
+    assert_eq!(sampler.spent(), 9);
+    assert!(sampler.threshold_weight().is_some());
+    assert_eq!(sampler.take().count(), 3);
+}
+
+/// Tests that an item that doesn't fit ends the sample rather than being
+/// skipped over - an overly expensive item that is all but certain to have
+/// the smallest key leaves the sample empty.
+#[test]
+fn test_budgeted_wswor_prefix() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: BudgetedWswor<f64, f64, i32> = BudgetedWswor::new(10.0);
+
+    sampler.feed(0, 100.0, 1e9, &mut rng).unwrap();
+    for i in 1 .. 10 {
+        assert!(!sampler.feed(i, 1.0, 1.0, &mut rng).unwrap());
+    }
+
+    assert!(sampler.is_empty());
+    assert_eq!(sampler.spent(), 0.0);
+}
+
+/// Tests cost validation - negative, NaN and infinite costs are rejected
+/// along with invalid weights, and leave the budget intact.
+#[test]
+fn test_budgeted_wswor_invalid_costs() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: BudgetedWswor<f64, f64, i32> = BudgetedWswor::new(10.0);
+
+    assert!(sampler.feed(1, -1.0, 1.0, &mut rng).is_err());
+    assert!(sampler.feed(2, f64::NAN, 1.0, &mut rng).is_err());
+    assert!(sampler.feed(3, 1.0, -1.0, &mut rng).is_err());
+    assert!(sampler.feed(4, 0.0, 1.0, &mut rng).is_ok());
+    assert!(matches!(
+        sampler.feed(5, f64::INFINITY, 1.0, &mut rng),
+        Err(HasInvalidWeights::Infinite)
+    ));
+
+    for i in 0 .. 100 {
+        sampler.feed(i, 1.0, 1.0, &mut rng).unwrap();
+    }
+
+    assert_eq!(sampler.spent(), 10.0);
+    assert!((10 ..= 11).contains(&sampler.len()));
+
+    let mut sampler: BudgetedWswor<f64, u32, i32> = BudgetedWswor::new(10);
+    assert!(sampler.feed(1, u32::MAX, 1.0, &mut rng).is_ok());
+}
+
+/// Tests that a budget that fits exactly one item gives the same
+/// distribution as sampling a single item - 1:2:3:4 weights, checked with a
+/// chi-square test.
+#[test]
+fn test_budgeted_wswor_proportionality() {
+    let mut rng = StdRng::seed_from_u64(12345);
+    let mut counts = HashMap::new();
+    let trials = 10000;
+
+    for _ in 0 .. trials {
+        let mut sampler: BudgetedWswor<f64, u32, char> = BudgetedWswor::new(1);
+        let items =
+            [(1.0, 1, 'A'), (2.0, 1, 'B'), (3.0, 1, 'C'), (4.0, 1, 'D')];
+        sampler.feed_iter(items.into_iter(), &mut rng).unwrap();
+
+        let results: Vec<_> = sampler.take().collect();
+        assert_eq!(results.len(), 1);
+        *counts.entry(results[0]).or_insert(0) += 1;
+    }
+
+    let mut chi_square = 0.0;
+    for (i, &item) in ['A', 'B', 'C', 'D'].iter().enumerate() {
+        let observed = *counts.get(&item).unwrap_or(&0) as f64;
+        let expected = trials as f64 * (i + 1) as f64 / 10.0;
+        chi_square += (observed - expected).powi(2) / expected;
+    }
+
+    // Critical value for 3 degrees of freedom at 0.01 significance level
+    assert!(
+        chi_square < 11.345,
+        "Chi-square value {} exceeds critical value",
+        chi_square
+    );
+}
+
+/// Tests that PoissonSampler reports min(1, weight * rate) as the inclusion
+/// probability and includes items with that frequency.
+#[test]
+fn test_poisson_sampler_probabilities() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: PoissonSampler<f64> = PoissonSampler::new(0.1).unwrap();
+    let trials = 10000;
+    let mut included = 0;
+
+    for _ in 0 .. trials {
+        let inclusion = sampler.feed(3.0, &mut rng).unwrap();
+        assert!((inclusion.probability - 0.3).abs() < 1e-12);
+        included += inclusion.included as usize;
+    }
+
+    let frequency = included as f64 / trials as f64;
+    assert!((frequency - 0.3).abs() < 0.02, "frequency {}", frequency);
+    assert_eq!(sampler.sampled(), included);
+
+    let certain = sampler.feed(20.0, &mut rng).unwrap();
+    assert!(certain.included);
+    assert_eq!(certain.probability, 1.0);
+
+    let never = sampler.feed(0.0, &mut rng).unwrap();
+    assert!(!never.included);
+    assert_eq!(never.probability, 0.0);
+
+    assert!(sampler.feed(-1.0, &mut rng).is_err());
+    assert!(PoissonSampler::new(f64::NAN).is_err());
+}
+
+/// Tests that a sampler built for an expected sample size of 50 over a
+/// known total weight averages about 50 items per run.
+#[test]
+fn test_poisson_sampler_expected_size() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let weights: Vec<f64> = (1 ..= 1000).map(|i| (i % 7 + 1) as f64).collect();
+    let total: f64 = weights.iter().sum();
+    let trials = 200;
+    let mut sampled = 0;
+
+    for _ in 0 .. trials {
+        let mut sampler =
+            PoissonSampler::with_expected_size(50.0, total).unwrap();
+        for &weight in weights.iter() {
+            sampler.feed(weight, &mut rng).unwrap();
+        }
+
+        assert!((sampler.expected_size() - 50.0).abs() < 1e-9);
+        assert_eq!(sampler.total_weight(), total);
+        sampled += sampler.sampled();
+    }
+
+    let average = sampled as f64 / trials as f64;
+    assert!((average - 50.0).abs() < 2.0, "average {}", average);
+}
+
+/// Tests the adaptive rate - every item is sampled at the target over the
+/// running total, so the first item is always included and the rate
+/// matches the target over the total weight at the end.
+#[test]
+fn test_poisson_sampler_adaptive() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: PoissonSampler<f64> =
+        PoissonSampler::adaptive(10.0).unwrap();
+
+    assert!(sampler.feed(1.0, &mut rng).unwrap().included);
+
+    for _ in 1 .. 1000 {
+        let inclusion = sampler.feed(1.0, &mut rng).unwrap();
+        assert!(
+            (inclusion.probability - sampler.rate().min(1.0)).abs() < 1e-12
+        );
+    }
+
+    assert!((sampler.rate() - 10.0 / 1000.0).abs() < 1e-12);
+    assert!(10.0 < sampler.expected_size());
+    assert!(sampler.expected_size() < 10.0 * (2.0 + 1000f64.ln()));
+
+    sampler.set_rate(0.5).unwrap();
+    assert_eq!(sampler.feed(1.0, &mut rng).unwrap().probability, 0.5);
+}
+
+/// Tests that hash-seeded keys make samples coordinated - the same IDs fed
+/// in a different order, or into a sampler that also sees other IDs with
+/// lower weights, come out the same for the same seed.
+#[test]
+fn test_streaming_wswor_feed_hashed_coordinated() {
+    let mut forward: StreamingWswor<f64, i32> = StreamingWswor::new(10);
+    let mut backward: StreamingWswor<f64, i32> = StreamingWswor::new(10);
+    let mut other_seed: StreamingWswor<f64, i32> = StreamingWswor::new(10);
+
+    for i in 0 .. 1000 {
+        forward.feed_hashed(i, &i, 1.0, 7).unwrap();
+        other_seed.feed_hashed(i, &i, 1.0, 8).unwrap();
+    }
+    for i in (0 .. 1000).rev() {
+        backward.feed_hashed(i, &i, 1.0, 7).unwrap();
+    }
+
+    let mut forward: Vec<_> = forward.take().collect();
+    let mut backward: Vec<_> = backward.take().collect();
+    let mut other_seed: Vec<_> = other_seed.take().collect();
+    forward.sort();
+    backward.sort();
+    other_seed.sort();
+
+    assert_eq!(forward, backward);
+    assert_ne!(forward, other_seed);
+    assert!(StreamingWswor::<f64, i32>::new(1)
+        .feed_hashed(0, "id", -1.0, 7)
+        .is_err());
+}
+
+/// Tests that coordination carries over to similar weights - two user
+/// tables with slightly perturbed weights share most of their samples, far
+/// more than independent samples would.
+#[test]
+fn test_streaming_wswor_feed_hashed_similar_weights() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut first: StreamingWswor<f64, String> = StreamingWswor::new(50);
+    let mut second: StreamingWswor<f64, String> = StreamingWswor::new(50);
+    let mut independent: StreamingWswor<f64, String> = StreamingWswor::new(50);
+
+    for i in 0 .. 1000 {
+        let user = format!("user-{}", i);
+        let weight = 1.0 + (i % 10) as f64;
+        first.feed_hashed(user.clone(), &user, weight, 1).unwrap();
+        second
+            .feed_hashed(user.clone(), &user, weight * 1.05, 1)
+            .unwrap();
+        independent.feed(user, weight, &mut rng).unwrap();
+    }
+
+    let first: Vec<_> = first.take().collect();
+    let second: Vec<_> = second.take().collect();
+    let independent: Vec<_> = independent.take().collect();
+
+    let shared = first.iter().filter(|u| second.contains(u)).count();
+    let by_chance = first.iter().filter(|u| independent.contains(u)).count();
+    assert!(45 <= shared, "only {} users shared", shared);
+    assert!(by_chance < 20, "{} users shared by chance", by_chance);
+}
+
+/// Tests that the variates derived from hashes are uniform - the fraction
+/// of hashed unit variates below 0.5 over many IDs should be about half.
+#[test]
+fn test_hashed_unit_uniform() {
+    let trials = 100000;
+    let below = (0 .. trials)
+        .filter(|i| hashed::hashed_unit(i, 3) < 0.5)
+        .count();
+
+    let fraction = below as f64 / trials as f64;
+    assert!((fraction - 0.5).abs() < 0.01, "fraction {}", fraction);
+    assert!((0 .. trials).all(|i| 0.0 < hashed::hashed_unit(&i, 3)));
+}
+
+/// Tests that the bytes hashed for an ID are fixed by the crate - integers of
+/// every width hash as the same word, strings as their bytes, and pinned
+/// values keep coordinated samples stable across Rust releases.
+#[test]
+fn test_hashed_id_bytes() {
+    let hash = |id: &dyn Fn(&mut hashed::SeededHasher)| {
+        let mut hasher = hashed::SeededHasher::new(3);
+        id(&mut hasher);
+        hasher.finish()
+    };
+    let word = hash(&|hasher| hasher.write_u64(5));
+
+    assert_eq!(word, hash(&|hasher| 5u8.write_id(hasher)));
+    assert_eq!(word, hash(&|hasher| 5u32.write_id(hasher)));
+    assert_eq!(word, hash(&|hasher| 5usize.write_id(hasher)));
+    assert_eq!(word, hash(&|hasher| 5i16.write_id(hasher)));
+    assert_eq!(
+        hash(&|hasher| (-1i8).write_id(hasher)),
+        hash(&|hasher| (-1i64).write_id(hasher))
+    );
+
+    let bytes = hash(&|hasher| hasher.write(b"user-1"));
+    assert_eq!(bytes, hash(&|hasher| "user-1".write_id(hasher)));
+    assert_eq!(
+        bytes,
+        hash(&|hasher| String::from("user-1").write_id(hasher))
+    );
+    assert_eq!(bytes, hash(&|hasher| b"user-1".write_id(hasher)));
+    assert_ne!(
+        hash(&|hasher| (1u64, 2u64).write_id(hasher)),
+        hash(&|hasher| (2u64, 1u64).write_id(hasher))
+    );
+
+    assert_eq!(hashed::hashed_unit(&42u64, 7), 0.033650237733623745);
+    assert_eq!(hashed::hashed_unit("user-42", 7), 0.8265677494988042);
+}
+
+/// Tests that ICWS signatures estimate weighted Jaccard similarity - two
+/// sets with a known similarity of 2/7 should match in about that fraction of
+/// the signature, a set should match itself entirely regardless of feeding
+/// order, and zero weights should leave the sketch untouched.
+#[test]
+fn test_icws_sketch_weighted_jaccard() {
+    let mut a = IcwsSketch::new(4000, 11);
+    let mut b = IcwsSketch::new(4000, 11);
+    let mut a_reversed = IcwsSketch::new(4000, 11);
+    let set_a = [(1.0, "x"), (2.0, "y"), (3.0, "z")];
+
+    a.feed_iter(set_a.into_iter()).unwrap();
+    a_reversed.feed_iter(set_a.into_iter().rev()).unwrap();
+    b.feed_iter([(1.0, "x"), (1.0, "y"), (1.0, "w"), (0.0, "z")].into_iter())
+        .unwrap();
+
+    let similarity = a.similarity(&b);
+    assert!(
+        (similarity - 2.0 / 7.0).abs() < 0.03,
+        "similarity {}",
+        similarity
+    );
+    assert_eq!(a.similarity(&a_reversed), 1.0);
+
+    let mut empty = IcwsSketch::new(4000, 11);
+    empty.feed("z", 0.0).unwrap();
+    assert!(empty.signature().all(|element| element.is_none()));
+    assert_eq!(empty.similarity(&a), 0.0);
+    assert!(empty.feed("z", f64::NAN).is_err());
+}
+
+/// Tests that ICWS similarity follows the weights - scaling every weight of a
+/// set by the same factor should give a similarity close to the ratio of the
+/// two scales.
+#[test]
+fn test_icws_sketch_scaled_weights() {
+    let mut small = IcwsSketch::new(4000, 5);
+    let mut large = IcwsSketch::new(4000, 5);
+
+    for i in 0 .. 20 {
+        let weight = 1.0 + i as f64;
+        small.feed(&i, weight).unwrap();
+        large.feed(&i, weight * 2.0).unwrap();
+    }
+
+    let similarity = small.similarity(&large);
+    assert!((similarity - 0.5).abs() < 0.03, "similarity {}", similarity);
+}
+
+/// Tests that keys are sampled by their aggregated weight - a key whose
+/// weight is spread over many small elements should be sampled as often as a
+/// key with the same total in a single element, and the estimates of the
+/// totals should average out to the true totals.
+#[test]
+fn test_aggregated_wswor_by_total_weight() {
+    let runs = 2000;
+    let mut hits = [0usize; 3];
+    let mut estimates = [0.0; 3];
+
+    for seed in 0 .. runs {
+        let mut sampler: AggregatedWswor<u32, f64> =
+            AggregatedWswor::new(2, seed);
+
+        for _ in 0 .. 50 {
+            sampler.feed(0, 0.2).unwrap();
+            sampler.feed(2, 0.02).unwrap();
+        }
+        sampler.feed(1, 10.0).unwrap();
+        for i in 3 .. 20 {
+            sampler.feed(i, 0.5).unwrap();
+        }
+
+        assert_eq!(sampler.len(), 2);
+        for (key, estimate) in sampler.take() {
+            if key < 3 {
+                hits[key as usize] += 1;
+                estimates[key as usize] += estimate;
+            }
+        }
+    }
+
+    assert!(
+        (hits[0] as f64 - hits[1] as f64).abs() < 0.1 * runs as f64,
+        "hits {:?}",
+        hits
+    );
+    assert!(hits[2] < hits[0] / 2, "hits {:?}", hits);
+
+    for (total, estimate) in [10.0, 10.0, 1.0].iter().zip(estimates) {
+        let mean = estimate / runs as f64;
+        assert!((mean - total).abs() < 0.1 * total, "mean {}", mean);
+    }
+}
+
+/// Tests that aggregated estimates are exact while nothing was left out,
+/// and that the same stream and seed always give the same sample.
+#[test]
+fn test_aggregated_wswor_exact_and_reproducible() {
+    let stream = [(1.0, "a"), (2.0, "b"), (3.0, "a"), (0.0, "c"), (4.0, "b")];
+
+    let mut sampler: AggregatedWswor<&str, f64> = AggregatedWswor::new(5, 3);
+    sampler.feed_iter(stream.into_iter()).unwrap();
+    assert_eq!(sampler.threshold_weight(), None);
+    assert_eq!(sampler.estimate(&"a"), Some(4.0));
+    assert_eq!(sampler.estimate(&"b"), Some(6.0));
+    assert_eq!(sampler.estimate(&"c"), None);
+
+    let sample = |seed| {
+        let mut sampler: AggregatedWswor<u32, f64> =
+            AggregatedWswor::new(3, seed);
+        for i in 0 .. 1000 {
+            sampler.feed(i % 37, 1.0 + (i % 5) as f64).unwrap();
+        }
+        let mut keys: Vec<_> = sampler.take().map(|(key, _)| key).collect();
+        keys.sort();
+        keys
+    };
+    assert_eq!(sample(9), sample(9));
+    assert!(AggregatedWswor::<u32, f64>::new(1, 0)
+        .feed(0, -1.0)
+        .is_err());
+}
+
+/// Tests that a multi-objective sample covers every objective - each
+/// objective keeps its own bottom-k set, identical objectives share a single
+/// set, and the union holds between k and k times the number of objectives
+/// items.
+#[test]
+fn test_multi_objective_wswor_union() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: MultiObjectiveWswor<f64, usize> =
+        MultiObjectiveWswor::new(10, 3);
+    let mut shared: MultiObjectiveWswor<f64, usize> =
+        MultiObjectiveWswor::new(10, 2);
+
+    for i in 0 .. 1000 {
+        let weight = 1.0 + i as f64;
+        sampler
+            .feed(i, &[weight, 1.0, 1000.0 - i as f64], &mut rng)
+            .unwrap();
+        shared.feed(i, &[weight, 2.0 * weight], &mut rng).unwrap();
+    }
+
+    assert!(10 <= sampler.len() && sampler.len() <= 30);
+    assert_eq!(shared.len(), 10);
+    assert!(sampler.threshold_weight(0).is_some());
+
+    let items: Vec<_> = sampler.take().collect();
+    for objective in 0 .. 3 {
+        let certain = items
+            .iter()
+            .filter(|item| item.probabilities[objective] == 1.0)
+            .count();
+        assert_eq!(certain, 0);
+    }
+    assert!(items.iter().all(|item| {
+        item.probabilities
+            .iter()
+            .all(|&p| 0.0 <= p && p <= item.probability)
+    }));
+    assert!(shared.feed(0, &[1.0, f64::INFINITY], &mut rng).is_err());
+}
+
+/// Tests that the union probabilities give unbiased estimates - the
+/// Horvitz-Thompson estimates of the total weight under every objective
+/// should average out to the true totals.
+#[test]
+fn test_multi_objective_wswor_estimates() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let runs = 2000;
+    let weights = |i: usize| [1.0 + (i % 7) as f64, 1.0, (i * i % 13) as f64];
+    let totals: Vec<f64> = (0 .. 3)
+        .map(|objective| (0 .. 200).map(|i| weights(i)[objective]).sum())
+        .collect();
+    let mut estimates = [0.0; 3];
+
+    for _ in 0 .. runs {
+        let mut sampler: MultiObjectiveWswor<f64, usize> =
+            MultiObjectiveWswor::new(5, 3);
+        for i in 0 .. 200 {
+            sampler.feed(i, &weights(i), &mut rng).unwrap();
+        }
+        for item in sampler.take() {
+            for (estimate, weight) in estimates.iter_mut().zip(&item.weights) {
+                *estimate += weight / item.probability;
+            }
+        }
+    }
+
+    for (total, estimate) in totals.iter().zip(estimates) {
+        let mean = estimate / runs as f64;
+        assert!((mean - total).abs() < 0.05 * total, "mean {}", mean);
+    }
+}
+
+/// Tests that replicates are independent samples - each holds `count`
+/// items, they differ from each other, and across replicates the heaviest item
+/// shows up about as often as a single sampler would pick it.
+#[test]
+fn test_replicated_wswor_independent() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: ReplicatedWswor<f64, i32> = ReplicatedWswor::new(1, 4000);
+
+    sampler
+        .feed_iter([(1.0, 1), (2.0, 2), (7.0, 3)].into_iter(), &mut rng)
+        .unwrap();
+
+    assert!(sampler.iter().all(|replicate| replicate.count() == 1));
+
+    let replicates = sampler.take();
+    assert_eq!(replicates.len(), 4000);
+
+    let heaviest = replicates.iter().filter(|r| r == &&[3]).count();
+    let expected = 4000 * 7 / 10;
+    assert!(
+        i32::abs(heaviest as i32 - expected) < 120,
+        "heaviest picked {} times",
+        heaviest
+    );
+}
+
+/// Tests that replicates share value storage - an item kept by every
+/// replicate is only cloned when the samples are taken out, and only for all
+/// but the last replicate.
+#[test]
+fn test_replicated_wswor_shared_values() {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+    };
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Counted(self.0.clone())
+        }
+    }
+
+    let clones = Rc::new(Cell::new(0));
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: ReplicatedWswor<f64, Counted> = ReplicatedWswor::new(2, 5);
+
+    assert!(sampler
+        .feed(Counted(clones.clone()), 1.0, &mut rng)
+        .unwrap());
+    assert_eq!(clones.get(), 0);
+
+    let replicates = sampler.take();
+    assert_eq!(replicates.len(), 5);
+    assert_eq!(clones.get(), 4);
+
+    let mut empty: ReplicatedWswor<f64, i32> = ReplicatedWswor::new(0, 3);
+    assert!(!empty.feed(1, 1.0, &mut rng).unwrap());
+    assert!(empty.feed(1, -1.0, &mut rng).is_err());
+}
+
+/// Tests that samples can be taken out in stream order - the sampled items
+/// should come out in the order they were fed, whichever path fed them.
+#[test]
+fn test_streaming_wswor_take_in_stream_order() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: StreamingWswor<f64, i32> = StreamingWswor::new(50);
+    let mut constant: StreamingWswor<f64, i32> =
+        StreamingWswor::new(20).detect_constant_weight();
+
+    for i in 0 .. 1000 {
+        sampler.feed(i, 1.0 + (i % 3) as f64, &mut rng).unwrap();
+        constant.feed(i, 2.0, &mut rng).unwrap();
+    }
+
+    for sampler in [sampler, constant] {
+        let results: Vec<_> = sampler.take_in_stream_order().collect();
+        assert!(results.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
+
+/// Tests that samples can be taken out in draw order - the first item out
+/// should follow the successive sampling probabilities, so with a dominant
+/// weight it should come first most of the time, and the order should match
+/// increasing keys.
+#[test]
+fn test_streaming_wswor_take_in_draw_order() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let trials = 10000;
+    let mut first = HashMap::new();
+
+    for _ in 0 .. trials {
+        let mut sampler: StreamingWswor<f64, i32> = StreamingWswor::new(3);
+        sampler
+            .feed_iter(
+                [(1.0, 1), (2.0, 2), (7.0, 3), (0.0, 4)].into_iter(),
+                &mut rng,
+            )
+            .unwrap();
+
+        let results: Vec<_> = sampler.take_in_draw_order().collect();
+        assert_eq!(results.len(), 3);
+        assert!(!results.contains(&4));
+        *first.entry(results[0]).or_insert(0) += 1;
+    }
+
+    for (val, weight) in [(1, 1.0), (2, 2.0), (3, 7.0)] {
+        let expected = trials as f64 * weight / 10.0;
+        let count = first[&val] as f64;
+        assert!(
+            (count - expected).abs() < 0.05 * trials as f64,
+            "{} drawn first {} times",
+            val,
+            count
+        );
+    }
+}
+
+/// Tests that ties in keys are broken by stream order - with more
+/// zero-weight items than room, the earliest ones should be kept, whatever
+/// order they sit in inside the heap.
+#[test]
+fn test_streaming_wswor_ties_by_stream_order() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: StreamingWswor<f64, i32> = StreamingWswor::new(3);
+
+    for i in 0 .. 10 {
+        sampler.feed(i, 0.0, &mut rng).unwrap();
+    }
+
+    assert_eq!(sampler.take_in_draw_order().collect::<Vec<_>>(), [0, 1, 2]);
+}
+
+/// Tests the RNG consumption contract - every feed draws exactly one
+/// variate, so zero weights and a zero sample size leave later items with the
+/// same randomness, and a rejected weight draws nothing.
+#[test]
+fn test_streaming_wswor_rng_contract() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut sampler: StreamingWswor<f64, i32> = StreamingWswor::new(0);
+
+    sampler.feed(0, 0.0, &mut rng).unwrap();
+    sampler.feed(1, 1.0, &mut rng).unwrap();
+    assert!(sampler.feed(2, f64::NAN, &mut rng).is_err());
+
+    let _: f64 = Exp1.sample(&mut other);
+    let _: f64 = Exp1.sample(&mut other);
+    assert_eq!(rng.next_u64(), other.next_u64());
+}
+
+/// Tests that samples are pinned for fixed seeds - these golden outputs
+/// must not change between releases with the same major version. Uses
+/// ChaCha8, since `StdRng` may change algorithm between rand releases.
+#[test]
+fn test_streaming_wswor_golden() {
+    let sample = |seed| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut sampler: StreamingWswor<f64, usize> = StreamingWswor::new(5);
+        for i in 0 .. 100 {
+            sampler.feed(i, (i % 7) as f64, &mut rng).unwrap();
+        }
+        sampler.take_in_draw_order().collect::<Vec<_>>()
+    };
+
+    assert_eq!(sample(0), [38, 97, 79, 33, 3]);
+    assert_eq!(sample(1), [88, 34, 87, 18, 54]);
+    assert_eq!(sample(42), [93, 55, 78, 87, 40]);
+}
+
+/// Tests that counter-based randomness makes sharded runs agree with a
+/// sequential one - shards fed in scrambled order and merged should give
+/// exactly the sequential sample, keys included, while another seed gives a
+/// different one.
+#[test]
+fn test_streaming_wswor_feed_at_shards() {
+    let weight = |i: usize| (i % 5) as f64;
+    let sampler = || StreamingWswor::<f64, usize>::new(20).counter_based(9);
+
+    let mut sequential = sampler();
+    for i in 0 .. 1000 {
+        sequential.feed_at(i, i, weight(i)).unwrap();
+    }
+
+    let mut shards: Vec<_> = (0 .. 4).map(|_| sampler()).collect();
+    for i in (0 .. 1000).rev() {
+        shards[i * 7 % 4].feed_at(i, i, weight(i)).unwrap();
+    }
+    let mut merged = sampler();
+    for shard in shards {
+        merged.merge(shard);
+    }
+
+    let mut other_seed =
+        StreamingWswor::<f64, usize>::new(20).counter_based(10);
+    for i in 0 .. 1000 {
+        other_seed.feed_at(i, i, weight(i)).unwrap();
+    }
+
+    assert_eq!(sequential.threshold_weight(), merged.threshold_weight());
+    let sequential: Vec<_> = sequential.take_in_draw_order().collect();
+    assert_eq!(sequential, merged.take_in_draw_order().collect::<Vec<_>>());
+    assert_ne!(
+        sequential,
+        other_seed.take_in_draw_order().collect::<Vec<_>>()
+    );
+}
+
+/// Tests that counter-based randomness samples by weight - over many seeds,
+/// the heaviest of three items should be picked with probability
+/// proportional to its weight.
+#[test]
+fn test_streaming_wswor_feed_at_distribution() {
+    let trials = 10000;
+    let heaviest = (0 .. trials)
+        .filter(|&seed| {
+            let mut sampler =
+                StreamingWswor::<f64, i32>::new(1).counter_based(seed);
+            sampler.feed_at(0, 1, 1.0).unwrap();
+            sampler.feed_at(1, 2, 2.0).unwrap();
+            sampler.feed_at(2, 3, 7.0).unwrap();
+            sampler.take().next() == Some(3)
+        })
+        .count();
+
+    let expected = trials as f64 * 0.7;
+    assert!(
+        (heaviest as f64 - expected).abs() < 0.02 * trials as f64,
+        "heaviest picked {} times",
+        heaviest
+    );
+}
+
+/// Tests that the inline reservoir matches the heap-backed one - fed the
+/// same stream from the same RNG state, both should keep the same items in
+/// the same draw order, zero weights, ties and integer weights included.
+#[test]
+fn test_array_wswor_matches_streaming() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut array: ArrayWswor<f64, usize, 8> = ArrayWswor::new();
+    let mut streaming: StreamingWswor<f64, usize> = StreamingWswor::new(8);
+
+    for i in 0 .. 1000 {
+        let weight = (i % 4) as f64;
+        array.feed(i, weight, &mut rng).unwrap();
+        streaming.feed(i, weight, &mut other).unwrap();
+    }
+
+    assert!(array.is_full());
+    assert_eq!(array.threshold_weight(), streaming.threshold_weight());
+    assert_eq!(
+        array.take_in_draw_order().collect::<Vec<_>>(),
+        streaming.take_in_draw_order().collect::<Vec<_>>()
+    );
+
+    let mut zeros: ArrayWswor<f64, usize, 3> = ArrayWswor::new();
+    zeros
+        .feed_iter((0 .. 10).map(|i| (0.0, i)), &mut rng)
+        .unwrap();
+    assert_eq!(zeros.take_in_draw_order().collect::<Vec<_>>(), [0, 1, 2]);
+
+    let mut empty: ArrayWswor<f64, usize, 0> = ArrayWswor::new();
+    assert!(!empty.feed(0, 1.0, &mut rng).unwrap());
+    assert!(empty.feed(0, f64::NAN, &mut rng).is_err());
+    assert_eq!(empty.take().count(), 0);
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut other = StdRng::seed_from_u64(7);
+    let mut array: ArrayWswor<u64, usize, 8> = ArrayWswor::new();
+    let mut streaming: StreamingWswor<u64, usize> = StreamingWswor::new(8);
+    for i in 0 .. 1000 {
+        let weight = (i % 4) as u64 * 1_000_000_007;
+        array.feed(i, weight, &mut rng).unwrap();
+        streaming.feed(i, weight, &mut other).unwrap();
+    }
+    assert_eq!(
+        array.take_in_draw_order().collect::<Vec<_>>(),
+        streaming.take_in_draw_order().collect::<Vec<_>>()
+    );
+}
+
+/// Tests that the inline reservoir owns its items properly - every item fed
+/// should be dropped exactly once, whether it was evicted, taken out, or
+/// left in the sampler when it was dropped.
+#[test]
+fn test_array_wswor_drops() {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+    };
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let mut sampler: ArrayWswor<f64, Counted, 4> = ArrayWswor::new();
+    for _ in 0 .. 100 {
+        sampler.feed(Counted(drops.clone()), 1.0, &mut rng).unwrap();
+    }
+    assert_eq!(drops.get(), 96);
+    assert_eq!(sampler.iter().count(), 4);
+
+    let mut taken = sampler.take_in_draw_order();
+    drop(taken.next());
+    assert_eq!(drops.get(), 97);
+    drop(taken);
+    assert_eq!(drops.get(), 100);
+}
+
+/// Tests that batch feeding samples by weight - across many runs over a
+/// slice longer than a chunk, each item should be picked about as often as
+/// its weight says, like with item by item feeding.
+#[test]
+fn test_streaming_wswor_feed_slice_distribution() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let weights: Vec<f64> = (0 .. 100).map(|i| (i % 4) as f64).collect();
+    let vals: Vec<usize> = (0 .. 100).collect();
+    let total: f64 = weights.iter().sum();
+    let trials = 20000;
+    let mut counts = [0usize; 100];
+
+    for _ in 0 .. trials {
+        let mut sampler: StreamingWswor<f64, usize> = StreamingWswor::new(1);
+        assert!(sampler.feed_slice(&weights, &vals, &mut rng).unwrap() >= 1);
+        counts[sampler.take().next().unwrap()] += 1;
+    }
+
+    for (count, weight) in counts.iter().zip(&weights) {
+        let expected = trials as f64 * weight / total;
+        assert!(
+            (*count as f64 - expected).abs() < 60.0,
+            "picked {} times, expected {}",
+            count,
+            expected
+        );
+    }
+}
+
+/// Tests the batch feeding bookkeeping - only kept items are cloned, stream
+/// indices carry on from earlier feeds, an invalid weight anywhere feeds
+/// nothing, and values past the last weight are left in the iterator.
+#[test]
+fn test_streaming_wswor_feed_batch() {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+    };
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Counted(self.0.clone())
+        }
+    }
+
+    let clones = Rc::new(Cell::new(0));
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut sampler: StreamingWswor<f64, Counted> = StreamingWswor::new(5);
+    let vals = vec![Counted(clones.clone()); 1000];
+    clones.set(0);
+
+    let kept = sampler.feed_slice(&[1.0; 1000], &vals, &mut rng).unwrap();
+    assert_eq!(clones.get(), kept);
+    assert!(kept < 200);
+    assert_eq!(sampler.len(), 5);
+
+    let mut ordered: StreamingWswor<f64, i32> = StreamingWswor::new(10);
+    ordered.feed(-1, 0.0, &mut rng).unwrap();
+    let mut vals = 0 .. 20;
+    ordered.feed_batch(&[0.0; 9], &mut vals, &mut rng).unwrap();
+    assert_eq!(vals.next(), Some(9));
+    let results: Vec<_> = ordered.take_in_draw_order().collect();
+    assert_eq!(results, [-1, 0, 1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let mut invalid: StreamingWswor<f64, i32> = StreamingWswor::new(10);
+    assert!(invalid
+        .feed_slice(&[1.0, 2.0, -1.0], &[1, 2, 3], &mut rng)
+        .is_err());
+    assert!(invalid.is_empty());
+
+    let mut constant: StreamingWswor<f64, i32> =
+        StreamingWswor::new(3).detect_constant_weight();
+    let vals: Vec<i32> = (0 .. 100).collect();
+    constant.feed_slice(&[2.0; 100], &vals, &mut rng).unwrap();
+    assert_eq!(constant.len(), 3);
+}
+
+/// Tests that the key-slab backend keeps the same sample as the heap - fed
+/// the same stream from the same RNG state, through the regular, skipping,
+/// shrinking and merging paths, both should give the same items in the same
+/// draw order.
+#[test]
+fn test_streaming_wswor_key_slab_backend() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut heap: StreamingWswor<f64, [u64; 16]> = StreamingWswor::new(50);
+    let mut slab: StreamingWswor<f64, [u64; 16]> =
+        StreamingWswor::new(50).backend(Backend::KeySlab);
+
+    for i in 0 .. 2000 {
+        let weight = (i % 5) as f64;
+        heap.feed([i; 16], weight, &mut rng).unwrap();
+        slab.feed([i; 16], weight, &mut other).unwrap();
+    }
+
+    assert_eq!(slab.len(), 50);
+    assert_eq!(slab.iter().count(), 50);
+    assert_eq!(heap.threshold_weight(), slab.threshold_weight());
+
+    heap.shrink_to(30);
+    slab.shrink_to(30);
+    assert_eq!(heap.threshold_weight(), slab.threshold_weight());
+
+    let constant = |backend| {
+        let mut sampler: StreamingWswor<f64, [u64; 16]> =
+            StreamingWswor::new(10)
+                .backend(backend)
+                .detect_constant_weight();
+        let mut rng = StdRng::seed_from_u64(7);
+        for i in 0 .. 1000 {
+            sampler.feed([i; 16], 3.0, &mut rng).unwrap();
+        }
+        sampler
+    };
+    heap.merge(constant(Backend::Heap));
+    slab.merge(constant(Backend::KeySlab));
+
+    assert_eq!(
+        heap.take_in_draw_order().collect::<Vec<_>>(),
+        slab.take_in_draw_order().collect::<Vec<_>>()
+    );
+}
+
+/// Tests that the buffer backend keeps the same sample as the heap, through
+/// the regular, batched, shrinking and merging paths, while never reporting
+/// more items than the sample size.
+#[test]
+fn test_streaming_wswor_buffer_backend() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut heap: StreamingWswor<f64, u64> = StreamingWswor::new(50);
+    let mut buffer: StreamingWswor<f64, u64> =
+        StreamingWswor::new(50).backend(Backend::Buffer);
+
+    for i in 0 .. 2000 {
+        let weight = (i % 5) as f64;
+        heap.feed(i, weight, &mut rng).unwrap();
+        buffer.feed(i, weight, &mut other).unwrap();
+        assert!(buffer.len() <= 50);
+    }
+
+    let weights: Vec<f64> = (0 .. 1000).map(|i| (i % 3) as f64).collect();
+    let vals: Vec<u64> = (2000 .. 3000).collect();
+    heap.feed_slice(&weights, &vals, &mut rng).unwrap();
+    buffer.feed_slice(&weights, &vals, &mut other).unwrap();
+
+    assert_eq!(buffer.len(), 50);
+    assert_eq!(buffer.iter().count(), 50);
+    assert_eq!(heap.threshold_weight(), buffer.threshold_weight());
+
+    let mut sampled: Vec<u64> = buffer.iter().copied().collect();
+    let mut expected: Vec<u64> = heap.iter().copied().collect();
+    sampled.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(sampled, expected);
+
+    heap.shrink_to(30);
+    buffer.shrink_to(30);
+    assert_eq!(heap.threshold_weight(), buffer.threshold_weight());
+
+    let mut constant: StreamingWswor<f64, u64> = StreamingWswor::new(10)
+        .backend(Backend::Buffer)
+        .detect_constant_weight();
+    for i in 0 .. 1000 {
+        constant.feed(i, 3.0, &mut rng).unwrap();
+    }
+    assert_eq!(constant.len(), 10);
+    assert_eq!(constant.iter().count(), 10);
+
+    let shard = || {
+        let mut sampler: StreamingWswor<f64, u64> = StreamingWswor::new(20);
+        let mut rng = StdRng::seed_from_u64(7);
+        for i in 3000 .. 3100 {
+            sampler.feed(i, 1.0, &mut rng).unwrap();
+        }
+        sampler
+    };
+    heap.merge(shard());
+    buffer.merge(shard());
+
+    assert_eq!(
+        heap.take_in_draw_order().collect::<Vec<_>>(),
+        buffer.take_in_draw_order().collect::<Vec<_>>()
+    );
+}
+
+/// Tests integer and rational weights - they should give the same sample as
+/// the equivalent floats from the same RNG state, through every way of
+/// feeding, and invalid ratios should be rejected.
+#[test]
+fn test_streaming_wswor_integer_and_rational_weights() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut third = StdRng::seed_from_u64(42);
+    let mut floats: StreamingWswor<f64, u64> = StreamingWswor::new(10);
+    let mut counts: StreamingWswor<u64, u64> = StreamingWswor::new(10);
+    let mut ratios: StreamingWswor<Ratio<u64>, u64> = StreamingWswor::new(10);
+
+    for i in 0 .. 500 {
+        let weight = i % 7;
+        floats.feed(i, weight as f64 / 4.0, &mut rng).unwrap();
+        counts.feed(i, weight, &mut other).unwrap();
+        ratios.feed(i, Ratio::new(weight, 4), &mut third).unwrap();
+    }
+
+    let weights: Vec<u64> = (0 .. 200).map(|i| i % 3).collect();
+    let vals: Vec<u64> = (500 .. 700).collect();
+    let float_weights: Vec<f64> =
+        weights.iter().map(|&w| w as f64 / 4.0).collect();
+    let ratio_weights: Vec<Ratio<u64>> =
+        weights.iter().map(|&w| Ratio::new(w, 4)).collect();
+    floats.feed_slice(&float_weights, &vals, &mut rng).unwrap();
+    counts.feed_slice(&weights, &vals, &mut other).unwrap();
+    ratios
+        .feed_slice(&ratio_weights, &vals, &mut third)
+        .unwrap();
+
+    // scaling every weight by the same factor doesn't change the sample
+    let floats: Vec<u64> = floats.take_in_draw_order().collect();
+    assert_eq!(floats, counts.take_in_draw_order().collect::<Vec<_>>());
+    assert_eq!(floats, ratios.take_in_draw_order().collect::<Vec<_>>());
+
+    let mut ratios: StreamingWswor<Ratio<u64>, u64> = StreamingWswor::new(10);
+    assert!(matches!(
+        ratios.feed(0, Ratio::new_raw(1, 0), &mut rng),
+        Err(HasInvalidWeights::Infinite)
+    ));
+    assert!(matches!(
+        ratios.feed(0, Ratio::new_raw(0, 0), &mut rng),
+        Err(HasInvalidWeights::NaN)
+    ));
+    assert!(ratios.is_empty());
+
+    // ratios of other integers, with the sign on either side
+    let mut signed: StreamingWswor<Ratio<i32>, u64> = StreamingWswor::new(10);
+    assert!(matches!(
+        signed.feed(0, Ratio::new(-1, 2), &mut rng),
+        Err(HasInvalidWeights::Negative)
+    ));
+    assert!(matches!(
+        signed.feed(0, Ratio::new_raw(1, -2), &mut rng),
+        Err(HasInvalidWeights::Negative)
+    ));
+    assert!(signed.feed(0, Ratio::new_raw(-1, -2), &mut rng).unwrap());
+    assert!(signed.feed(1, Ratio::new(0, -2), &mut rng).unwrap());
+    assert_eq!(signed.len(), 2);
+
+    // counts past 2^53 still work, and a huge count all but always wins
+    let huge = u64::MAX - 1;
+    let mut wins = 0;
+    for _ in 0 .. 100 {
+        let mut single: SingleStreamingWs<u64, &str> = SingleStreamingWs::new();
+        single.feed("small", 1, &mut rng).unwrap();
+        single.feed("huge", huge, &mut rng).unwrap();
+        wins += (single.take() == Some("huge")) as usize;
+    }
+    assert_eq!(wins, 100);
+
+    let sample: Vec<u8> = wswor((0 .. 10u8).map(|i| (i, i)), &mut rng, 9)
+        .unwrap()
+        .collect();
+    assert!(!sample.contains(&0));
+}
+
+/// Tests integer weights in the samplers built on top of exponential keys -
+/// each should give the same sample as the equivalent floats from the same
+/// RNG state.
+#[test]
+fn test_integer_weights_across_samplers() {
+    let weights: Vec<u64> = (0 .. 100).map(|i| i % 7).collect();
+    let floats: Vec<f64> = weights.iter().map(|&w| w as f64).collect();
+    let rngs = || (StdRng::seed_from_u64(42), StdRng::seed_from_u64(42));
+
+    for algorithm in INDEX_ALGORITHMS {
+        let (mut rng, mut other) = rngs();
+        assert_eq!(
+            sample_indices(&weights, 10, algorithm, &mut rng).unwrap(),
+            sample_indices(&floats, 10, algorithm, &mut other).unwrap(),
+            "{:?}",
+            algorithm
+        );
+    }
+
+    let (mut rng, mut other) = rngs();
+    let table = AliasTable::new(weights.iter().copied().zip(0 ..)).unwrap();
+    let float_table =
+        AliasTable::new(floats.iter().copied().zip(0 ..)).unwrap();
+    assert_eq!(
+        table.sample_n(50, &mut rng).collect::<Vec<_>>(),
+        float_table.sample_n(50, &mut other).collect::<Vec<_>>()
+    );
+
+    let (mut rng, mut other) = rngs();
+    let mut capped: CategoryCappedWswor<u64, u64, usize> =
+        CategoryCappedWswor::new(10, 3);
+    let mut float_capped: CategoryCappedWswor<u64, f64, usize> =
+        CategoryCappedWswor::new(10, 3);
+    let mut budgeted: BudgetedWswor<u64, u32, usize> = BudgetedWswor::new(20);
+    let mut float_budgeted: BudgetedWswor<f64, u32, usize> =
+        BudgetedWswor::new(20);
+    let mut replicated: ReplicatedWswor<u64, usize> =
+        ReplicatedWswor::new(5, 3);
+    let mut float_replicated: ReplicatedWswor<f64, usize> =
+        ReplicatedWswor::new(5, 3);
+    let mut allocated: AllocatedWswor<u64, u64, usize> =
+        AllocatedWswor::new(10, Allocation::Proportional);
+    let mut float_allocated: AllocatedWswor<u64, f64, usize> =
+        AllocatedWswor::new(10, Allocation::Proportional);
+
+    for (i, (&weight, &float)) in weights.iter().zip(&floats).enumerate() {
+        let category = i as u64 % 4;
+        capped.feed(i, category, weight, &mut rng).unwrap();
+        float_capped.feed(i, category, float, &mut other).unwrap();
+        budgeted.feed(i, 3, weight, &mut rng).unwrap();
+        float_budgeted.feed(i, 3, float, &mut other).unwrap();
+        replicated.feed(i, weight, &mut rng).unwrap();
+        float_replicated.feed(i, float, &mut other).unwrap();
+        allocated.feed(category, i, weight, &mut rng).unwrap();
+        float_allocated
+            .feed(category, i, float, &mut other)
+            .unwrap();
+    }
+
+    let sorted = |iter: &mut dyn Iterator<Item = usize>| {
+        let mut items: Vec<_> = iter.collect();
+        items.sort();
+        items
+    };
+    assert_eq!(sorted(&mut capped.take()), sorted(&mut float_capped.take()));
+    assert_eq!(
+        sorted(&mut budgeted.take()),
+        sorted(&mut float_budgeted.take())
+    );
+    assert_eq!(
+        replicated
+            .take()
+            .into_iter()
+            .map(|r| sorted(&mut r.into_iter()))
+            .collect::<Vec<_>>(),
+        float_replicated
+            .take()
+            .into_iter()
+            .map(|r| sorted(&mut r.into_iter()))
+            .collect::<Vec<_>>()
+    );
+    let mut allocated: Vec<_> = allocated.take().into_iter().collect();
+    let mut float_allocated: Vec<_> =
+        float_allocated.take().into_iter().collect();
+    allocated.sort();
+    float_allocated.sort();
+    assert_eq!(allocated, float_allocated);
+
+    let (mut rng, mut other) = rngs();
+    let mut multi: MultiObjectiveWswor<u64, usize> =
+        MultiObjectiveWswor::new(5, 2);
+    let mut float_multi: MultiObjectiveWswor<f64, usize> =
+        MultiObjectiveWswor::new(5, 2);
+
+    for (i, &weight) in weights.iter().enumerate() {
+        let objectives = [weight, 6 - weight];
+        let float_objectives = objectives.map(|w| w as f64);
+        multi.feed(i, &objectives, &mut rng).unwrap();
+        float_multi.feed(i, &float_objectives, &mut other).unwrap();
+    }
+
+    assert_eq!(
+        sorted(&mut multi.take().map(|item| item.val)),
+        sorted(&mut float_multi.take().map(|item| item.val))
+    );
+}
+
+/// Tests half-precision weights - they should give the same sample as the
+/// same weights in `f32` from the same RNG state, keep finite keys for the
+/// smallest `f16` weights, and be validated like any float.
+#[cfg(feature = "half")]
+#[test]
+fn test_streaming_wswor_half_weights() {
+    use half::{
+        bf16,
+        f16,
+    };
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut third = StdRng::seed_from_u64(42);
+    let mut singles: StreamingWswor<f32, u64> = StreamingWswor::new(10);
+    let mut halves: StreamingWswor<f16, u64> = StreamingWswor::new(10);
+    let mut brains: StreamingWswor<bf16, u64> = StreamingWswor::new(10);
+
+    for i in 0 .. 500 {
+        let weight = (i % 7) as f32 / 8.0;
+        singles.feed(i, weight, &mut rng).unwrap();
+        halves.feed(i, f16::from_f32(weight), &mut other).unwrap();
+        brains.feed(i, bf16::from_f32(weight), &mut third).unwrap();
+    }
+
+    let singles: Vec<u64> = singles.take_in_draw_order().collect();
+    assert_eq!(singles, halves.take_in_draw_order().collect::<Vec<_>>());
+    assert_eq!(singles, brains.take_in_draw_order().collect::<Vec<_>>());
+
+    // an `Exp1` variate over the smallest subnormal `f16` would be infinite
+    // in `f16`, but not in `f32`
+    let mut tiny: StreamingWswor<f16, u64> = StreamingWswor::new(1);
+    tiny.feed(0, f16::from_bits(1), &mut rng).unwrap();
+    assert!(tiny.threshold_weight().unwrap().is_finite());
+
+    // `bf16` shares the exponent range of `f32`, so its keys need `f64`
+    let mut tiny: StreamingWswor<bf16, u64> = StreamingWswor::new(1);
+    tiny.feed(0, bf16::from_bits(1), &mut rng).unwrap();
+    assert!(tiny.threshold_weight().unwrap().is_finite());
+
+    let mut single: SingleStreamingWs<bf16, u64> = SingleStreamingWs::new();
+    assert!(matches!(
+        single.feed(0, bf16::NAN, &mut rng),
+        Err(HasInvalidWeights::NaN)
+    ));
+    assert!(matches!(
+        single.feed(0, bf16::INFINITY, &mut rng),
+        Err(HasInvalidWeights::Infinite)
+    ));
+    assert!(matches!(
+        single.feed(0, -bf16::ONE, &mut rng),
+        Err(HasInvalidWeights::Negative)
+    ));
+    assert!(single.get().is_none());
+}
+
+/// Tests log keys - they should keep the same sample as regular keys for
+/// moderate weights, and keep keys ordered for weights from subnormal to near
+/// the largest float, where regular keys overflow and the sample degenerates
+/// into the first items fed.
+#[test]
+fn test_streaming_wswor_log_keys() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut other = StdRng::seed_from_u64(42);
+    let mut linear: StreamingWswor<f64, u64> = StreamingWswor::new(10);
+    let mut log: StreamingWswor<f64, u64> = StreamingWswor::new(10).log_keys();
+
+    for i in 0 .. 1000 {
+        let weight = (i % 7) as f64;
+        linear.feed(i, weight, &mut rng).unwrap();
+        log.feed(i, weight, &mut other).unwrap();
+    }
+
+    assert_eq!(
+        linear.take_in_draw_order().collect::<Vec<_>>(),
+        log.take_in_draw_order().collect::<Vec<_>>()
+    );
+
+    // weights far enough apart that they are drawn by decreasing weight
+    let mut weights = vec![f64::from_bits(1), f64::MIN_POSITIVE];
+    weights.extend((-30 ..= 30).map(|e| 10f64.powi(10 * e)));
+    weights.push(f64::MAX);
+
+    let mut log: StreamingWswor<f64, usize> =
+        StreamingWswor::new(weights.len()).log_keys();
+    for (i, &weight) in weights.iter().enumerate() {
+        log.feed(i, weight, &mut rng).unwrap();
+    }
+    assert!(log.threshold_weight().unwrap().is_finite());
+    assert_eq!(
+        log.take_in_draw_order().collect::<Vec<_>>(),
+        (0 .. weights.len()).rev().collect::<Vec<_>>()
+    );
+
+    let mut batch: StreamingWswor<f64, usize> =
+        StreamingWswor::new(3).log_keys();
+    let vals: Vec<usize> = (0 .. weights.len()).collect();
+    batch.feed_slice(&weights, &vals, &mut rng).unwrap();
+    assert_eq!(
+        batch.take_in_draw_order().collect::<Vec<_>>(),
+        [weights.len() - 1, weights.len() - 2, weights.len() - 3]
+    );
+
+    // every key of a subnormal weight is infinite, so regular keys only break
+    // ties, while log keys still sample at random, skipping or not
+    let subnormal = f64::from_bits(1);
+    let first: Vec<u64> = (0 .. 10).collect();
+    let sample = |mut sampler: StreamingWswor<f64, u64>| {
+        let mut rng = StdRng::seed_from_u64(7);
+        for i in 0 .. 10_000 {
+            sampler.feed(i, subnormal, &mut rng).unwrap();
+        }
+        assert_eq!(sampler.len(), 10);
+        sampler.take_in_stream_order().collect::<Vec<_>>()
+    };
+    assert_eq!(sample(StreamingWswor::new(10)), first);
+    assert_ne!(sample(StreamingWswor::new(10).log_keys()), first);
+    assert_ne!(
+        sample(StreamingWswor::new(10).log_keys().detect_constant_weight()),
+        first
+    );
+}
+
+/// Tests that entries with NaN keys are ordered after every other key instead
+/// of panicking.
+#[test]
+fn test_wswor_entry_nan_keys() {
+    let entry = |weight, index| WsworEntry {
+        weight,
+        index,
+        val: (),
+    };
+
+    let mut entries = [
+        entry(f64::NAN, 0),
+        entry(f64::INFINITY, 1),
+        entry(1.0, 2),
+        entry(f64::NAN, 3),
+        entry(0.0, 4),
+    ];
+    entries.sort();
+
+    let order: Vec<usize> = entries.iter().map(|entry| entry.index).collect();
+    assert_eq!(order, [4, 2, 1, 0, 3]);
+    assert!(entry(f64::NAN, 0) == entry(f64::NAN, 0));
+}
+
+/// Tests that merging a sampler with log keys into one without panics.
+#[test]
+#[should_panic(expected = "log_keys")]
+fn test_streaming_wswor_log_keys_merge_mismatch() {
+    let mut linear: StreamingWswor<f64, u64> = StreamingWswor::new(3);
+    linear.merge(StreamingWswor::new(3).log_keys());
+}
@@ -0,0 +1,139 @@
+// Based on Li's "Algorithm L" for unweighted reservoir sampling.
+//
+// - Li, K.-H. (1994). Reservoir-sampling algorithms of time complexity O(n(1 +
+//   log(N/n))). ACM Transactions on Mathematical Software, 20(4), 481–493.
+
+use alloc::collections::BinaryHeap;
+
+use num::Float;
+use rand::{
+    distr::Distribution,
+    RngCore,
+};
+use rand_distr::Exp1;
+
+use crate::WsworEntry;
+
+/// Number of items to pass over before one beats the reservoir threshold, when
+/// each item independently does so with probability `1 - exp(-rate)`.
+///
+/// `rate` is the product of the item weight and the threshold key.
+pub(crate) fn skip_length<F, R>(
+    rate: F,
+    rng: &mut R,
+) -> usize
+where
+    F: Float,
+    R: RngCore,
+    Exp1: Distribution<F>,
+{
+    let random: F = Exp1.sample(rng);
+    (random / rate).floor().to_usize().unwrap_or(usize::MAX)
+}
+
+/// An `Exp1` variate conditioned to lie below `bound`.
+///
+/// By memorylessness, an exponential variate taken modulo `bound` is exactly
+/// an exponential variate truncated to `[0, bound)`.
+pub(crate) fn truncated_exp1<F, R>(
+    bound: F,
+    rng: &mut R,
+) -> F
+where
+    F: Float,
+    R: RngCore,
+    Exp1: Distribution<F>,
+{
+    let random: F = Exp1.sample(rng);
+    random % bound
+}
+
+/// One-pass Uniform Random Sampler Without Replacement.
+///
+/// This is Li's Algorithm L: once the reservoir is full, the number of items
+/// to skip before the next replacement is drawn from a geometric distribution,
+/// so no randomness is spent on items that would be rejected anyway. Unlike
+/// the textbook version, the keys of the sampled items are kept explicitly,
+/// which makes the reservoir interchangeable with that of a
+/// [`StreamingWswor`](crate::StreamingWswor) fed with constant weights.
+pub struct UniformReservoir<T> {
+    count: usize,
+    seen: usize,
+    heap: BinaryHeap<WsworEntry<f64, T>>,
+    skip: Option<usize>,
+}
+
+impl<T> UniformReservoir<T> {
+    pub fn new(count: usize) -> UniformReservoir<T> {
+        UniformReservoir {
+            count,
+            seen: 0,
+            heap: BinaryHeap::with_capacity(count + 1),
+            skip: None,
+        }
+    }
+
+    pub fn feed_iter<R: RngCore>(
+        &mut self,
+        iter: impl Iterator<Item = T>,
+        rng: &mut R,
+    ) {
+        for v in iter {
+            self.feed(v, rng);
+        }
+    }
+
+    pub fn feed<R: RngCore>(
+        &mut self,
+        val: T,
+        rng: &mut R,
+    ) -> bool {
+        let index = self.seen;
+        self.seen += 1;
+
+        if self.count == 0 {
+            return false;
+        }
+
+        // until the reservoir is full, everything goes in
+        if !self.is_full() {
+            self.heap.push(WsworEntry {
+                val,
+                index,
+                weight: Exp1.sample(rng),
+            });
+
+            return true;
+        }
+
+        let threshold = self.heap.peek().unwrap().weight;
+        let skip = self.skip.get_or_insert_with(|| skip_length(threshold, rng));
+
+        if *skip > 0 {
+            *skip -= 1;
+            return false;
+        }
+
+        self.skip = None;
+        self.heap.push(WsworEntry {
+            val,
+            index,
+            weight: truncated_exp1(threshold, rng),
+        });
+        self.heap.pop();
+
+        true
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count <= self.heap.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.heap.iter().map(|entry| &entry.val)
+    }
+
+    pub fn take(self) -> impl Iterator<Item = T> {
+        self.heap.into_iter().map(|entry| entry.val)
+    }
+}
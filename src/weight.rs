@@ -0,0 +1,140 @@
+use num::{
+    rational::Ratio,
+    Float,
+    Integer,
+    ToPrimitive,
+};
+
+use crate::HasInvalidWeights;
+
+/// A weight that items can be sampled by.
+///
+/// Keys are computed in the floating-point type [`Key`](Weight::Key), so a
+/// weight only has to say whether it is valid, whether it is zero and how it
+/// converts to that type. Integer weights are only rounded by that conversion,
+/// so even counts past `2^53` are off by a relative `2^-53` at most, and ratios
+/// by a few times that - far below the resolution of the exponential variates
+/// they scale.
+pub trait Weight: Copy + PartialEq {
+    /// The type keys are computed in.
+    type Key: Float;
+
+    /// Checks that items can be sampled by this weight.
+    fn validate(&self) -> Result<(), HasInvalidWeights>;
+
+    fn is_zero(&self) -> bool;
+
+    /// The weight in the type keys are computed in.
+    fn to_key(&self) -> Self::Key;
+}
+
+macro_rules! float_weight {
+    ($($t:ty),*) => {
+        $(
+            impl Weight for $t {
+                type Key = $t;
+
+                fn validate(&self) -> Result<(), HasInvalidWeights> {
+                    HasInvalidWeights::check_weight(self)
+                }
+
+                fn is_zero(&self) -> bool {
+                    *self == 0.0
+                }
+
+                fn to_key(&self) -> $t {
+                    *self
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! unsigned_weight {
+    ($($t:ty),*) => {
+        $(
+            impl Weight for $t {
+                type Key = f64;
+
+                // every unsigned integer is a valid weight
+                fn validate(&self) -> Result<(), HasInvalidWeights> {
+                    Ok(())
+                }
+
+                fn is_zero(&self) -> bool {
+                    *self == 0
+                }
+
+                fn to_key(&self) -> f64 {
+                    *self as f64
+                }
+            }
+        )*
+    };
+}
+
+// keys of half-precision weights are computed in a wider type, so that an
+// `Exp1` variate over a small weight doesn't overflow: `f32` covers `f16` with
+// room to spare, but `bf16` shares the exponent range of `f32` and needs `f64`
+#[cfg(feature = "half")]
+macro_rules! half_weight {
+    ($($t:ty => $k:ty),*) => {
+        $(
+            impl Weight for $t {
+                type Key = $k;
+
+                fn validate(&self) -> Result<(), HasInvalidWeights> {
+                    HasInvalidWeights::check_weight(&self.to_f32())
+                }
+
+                fn is_zero(&self) -> bool {
+                    self.to_f32() == 0.0
+                }
+
+                fn to_key(&self) -> $k {
+                    self.to_f32().into()
+                }
+            }
+        )*
+    };
+}
+
+float_weight!(f32, f64);
+unsigned_weight!(u8, u16, u32, u64, usize);
+#[cfg(feature = "half")]
+half_weight!(half::f16 => f32, half::bf16 => f64);
+
+impl<T> Weight for Ratio<T>
+where
+    T: Integer + ToPrimitive + Copy,
+{
+    type Key = f64;
+
+    /// A zero denominator makes the weight NaN if the numerator is zero too,
+    /// and infinite otherwise. Ratios built with `Ratio::new_raw` can carry
+    /// their sign on either side.
+    fn validate(&self) -> Result<(), HasInvalidWeights> {
+        let (numer, denom) = (*self.numer(), *self.denom());
+
+        match (numer.is_zero(), denom.is_zero()) {
+            (true, true) => Err(HasInvalidWeights::NaN),
+            (false, true) => Err(HasInvalidWeights::Infinite),
+            (false, false) if (numer < T::zero()) != (denom < T::zero()) => {
+                Err(HasInvalidWeights::Negative)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.numer().is_zero()
+    }
+
+    // every primitive integer converts to `f64`, if only approximately
+    fn to_key(&self) -> f64 {
+        let numer = self.numer().to_f64().unwrap_or(f64::NAN);
+        let denom = self.denom().to_f64().unwrap_or(f64::NAN);
+
+        numer / denom
+    }
+}